@@ -0,0 +1,566 @@
+// Disk model shared by problem9.rs and problem9part2.rs, pulled in with
+// `mod disk;`. Files are lists of spans, so a file fragmented by block level
+// compaction is just a file with several spans. Free space is kept in one
+// min-heap of span starts per span length, so the leftmost span of at least
+// some length is found by peeking at a handful of heaps.
+#![allow(dead_code)]
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+// Which free span a whole file moves into, among those that fit it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fit {
+    // The leftmost.
+    First,
+    // The smallest, leftmost among equals.
+    Best,
+    // The largest, leftmost among equals.
+    Worst,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Strategy {
+    pub fit: Fit,
+    // Keep making passes over all files until none of them can move.
+    pub multi_pass: bool,
+    // Only move a file if it ends up at most this many blocks further left.
+    pub window: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub moves: usize,
+    pub passes: usize,
+    // Free space left before the last data block.
+    pub free_spans: usize,
+    pub free_blocks: usize,
+    pub largest_free_span: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+// A piece of file `id` moved from `from` to `to`, both the same length.
+#[derive(Debug, Clone, Copy)]
+pub struct Move {
+    pub id: usize,
+    pub from: Span,
+    pub to: Span,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "file {}: {}..{} -> {}..{}",
+            self.id,
+            self.from.start,
+            self.from.start + self.from.len,
+            self.to.start,
+            self.to.start + self.to.len
+        )
+    }
+}
+
+pub struct FreeList {
+    // `buckets[len]` holds the starts of all free spans of length `len`.
+    buckets: Vec<BinaryHeap<Reverse<usize>>>,
+}
+
+impl FreeList {
+    pub fn new() -> FreeList {
+        FreeList {
+            buckets: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, span: Span) {
+        if span.len == 0 {
+            return;
+        }
+        if self.buckets.len() <= span.len {
+            self.buckets.resize(span.len + 1, BinaryHeap::new());
+        }
+        self.buckets[span.len].push(Reverse(span.start));
+    }
+
+    // Removes and returns the leftmost span of at least `min_len` blocks that
+    // starts before `limit`.
+    pub fn take_leftmost(&mut self, min_len: usize, limit: usize) -> Option<Span> {
+        let mut best: Option<Span> = None;
+        for len in min_len..self.buckets.len() {
+            if let Some(&Reverse(start)) = self.buckets[len].peek() {
+                if start < limit && best.map_or(true, |b| start < b.start) {
+                    best = Some(Span {
+                        start: start,
+                        len: len,
+                    });
+                }
+            }
+        }
+        if let Some(span) = best {
+            self.buckets[span.len].pop();
+        }
+        return best;
+    }
+
+    // All free spans, sorted by start.
+    pub fn spans(&self) -> Vec<Span> {
+        let mut spans: Vec<Span> = Vec::new();
+        for (len, bucket) in self.buckets.iter().enumerate() {
+            for &Reverse(start) in bucket.iter() {
+                spans.push(Span {
+                    start: start,
+                    len: len,
+                });
+            }
+        }
+        spans.sort_by_key(|s| s.start);
+        return spans;
+    }
+}
+
+// Free space for the strategies the heaps can't serve. Neighbouring spans are
+// merged, which repeated passes need to reuse the space files leave behind,
+// and each length bucket is ordered by start so windowed moves can ask for
+// the leftmost span inside a range. Merged spans aren't capped at 9 blocks
+// though, so lookups check more buckets than `FreeList` does.
+pub struct FreeIndex {
+    fit: Fit,
+    window: Option<usize>,
+    // Starts of the free spans of each length, with empty lengths removed.
+    buckets: BTreeMap<usize, BTreeSet<usize>>,
+    // Length of each free span by start, to merge neighbours on push.
+    by_start: BTreeMap<usize, usize>,
+}
+
+impl FreeIndex {
+    pub fn new(fit: Fit, window: Option<usize>) -> FreeIndex {
+        FreeIndex {
+            fit: fit,
+            window: window,
+            buckets: BTreeMap::new(),
+            by_start: BTreeMap::new(),
+        }
+    }
+
+    // Adds `span`, merging it with any free space directly either side.
+    pub fn push(&mut self, mut span: Span) {
+        if span.len == 0 {
+            return;
+        }
+        let before = self
+            .by_start
+            .range(..span.start)
+            .next_back()
+            .map(|(s, l)| (*s, *l));
+        if let Some((start, len)) = before {
+            if start + len == span.start {
+                self.remove(start, len);
+                span.start = start;
+                span.len += len;
+            }
+        }
+        let after = self.by_start.get(&(span.start + span.len)).cloned();
+        if let Some(len) = after {
+            self.remove(span.start + span.len, len);
+            span.len += len;
+        }
+
+        self.buckets
+            .entry(span.len)
+            .or_insert(BTreeSet::new())
+            .insert(span.start);
+        self.by_start.insert(span.start, span.len);
+    }
+
+    fn remove(&mut self, start: usize, len: usize) {
+        let bucket = self.buckets.get_mut(&len).unwrap();
+        bucket.remove(&start);
+        if bucket.is_empty() {
+            self.buckets.remove(&len);
+        }
+        self.by_start.remove(&start);
+    }
+
+    // Removes and returns a span of at least `min_len` blocks starting in
+    // `from..to`, picked according to the fit.
+    pub fn take(&mut self, min_len: usize, from: usize, to: usize) -> Option<Span> {
+        if from >= to {
+            return None;
+        }
+        let mut hits = self.buckets.range(min_len..).filter_map(|(&len, bucket)| {
+            bucket.range(from..to).next().map(|&start| Span {
+                start: start,
+                len: len,
+            })
+        });
+        // Buckets go up in length, so the best fit is the first hit and the
+        // worst fit the last.
+        let best = match self.fit {
+            Fit::First => hits.min_by_key(|s| s.start),
+            Fit::Best => hits.next(),
+            Fit::Worst => hits.next_back(),
+        };
+        if let Some(span) = best {
+            self.remove(span.start, span.len);
+        }
+        return best;
+    }
+
+    // All free spans, sorted by start.
+    pub fn spans(&self) -> Vec<Span> {
+        return self
+            .by_start
+            .iter()
+            .map(|(start, len)| Span {
+                start: *start,
+                len: *len,
+            })
+            .collect();
+    }
+}
+
+// Where whole file moves take their free space from, see `Disk::move_files`.
+trait FreeSpace {
+    fn give_back(&mut self, span: Span);
+    // Removes and returns the span `file` should move into, if any.
+    fn take_for(&mut self, file: Span) -> Option<Span>;
+}
+
+impl FreeSpace for FreeList {
+    fn give_back(&mut self, span: Span) {
+        self.push(span);
+    }
+    fn take_for(&mut self, file: Span) -> Option<Span> {
+        return self.take_leftmost(file.len, file.start);
+    }
+}
+
+impl FreeSpace for FreeIndex {
+    fn give_back(&mut self, span: Span) {
+        self.push(span);
+    }
+    fn take_for(&mut self, file: Span) -> Option<Span> {
+        let from = self.window.map_or(0, |w| file.start.saturating_sub(w));
+        return self.take(file.len, from, file.start);
+    }
+}
+
+pub struct Disk {
+    // Spans of each file, indexed by file id.
+    pub files: Vec<Vec<Span>>,
+    pub free: FreeList,
+    pub size: usize,
+    // Every move made while compacting, in order.
+    pub trace: Vec<Move>,
+}
+
+impl Disk {
+    // Parses a dense disk map, e.g. "2333133121414131402", where digits
+    // alternate between file and free space lengths.
+    pub fn parse(contents: &str) -> Disk {
+        let mut disk = Disk {
+            files: Vec::new(),
+            free: FreeList::new(),
+            size: 0,
+            trace: Vec::new(),
+        };
+        for (i, c) in contents.trim().chars().enumerate() {
+            let span = Span {
+                start: disk.size,
+                len: c.to_digit(10).expect("fail") as usize,
+            };
+            if i % 2 == 0 {
+                disk.files.push(vec![span]);
+            } else {
+                disk.free.push(span);
+            }
+            disk.size += span.len;
+        }
+        return disk;
+    }
+
+    // Moves blocks one at a time from the end of the disk into the leftmost
+    // free space, splitting files as needed, until no free space is left of
+    // any data.
+    pub fn compact_blocks(&mut self) {
+        for id in (0..self.files.len()).rev() {
+            let mut spans = std::mem::replace(&mut self.files[id], Vec::new());
+            let mut moved = Vec::new();
+            while let Some(mut tail) = spans.pop() {
+                while tail.len > 0 {
+                    let free = match self.free.take_leftmost(1, tail.start) {
+                        Some(free) => free,
+                        None => break,
+                    };
+                    let used = std::cmp::min(free.len, tail.len);
+                    tail.len -= used;
+                    let step = Move {
+                        id: id,
+                        from: Span {
+                            start: tail.start + tail.len,
+                            len: used,
+                        },
+                        to: Span {
+                            start: free.start,
+                            len: used,
+                        },
+                    };
+                    moved.push(step.to);
+                    self.free.push(step.from);
+                    self.trace.push(step);
+                    self.free.push(Span {
+                        start: free.start + used,
+                        len: free.len - used,
+                    });
+                }
+                if tail.len > 0 {
+                    // Nothing free further left, so the rest stays put.
+                    spans.push(tail);
+                    break;
+                }
+            }
+            spans.extend(moved);
+            spans.sort_by_key(|s| s.start);
+            self.files[id] = spans;
+        }
+    }
+
+    // Moves each whole file once, highest id first, into the leftmost free
+    // span that fits it, if that's further left.
+    pub fn compact_files(&mut self) {
+        self.compact_files_with(Strategy {
+            fit: Fit::First,
+            multi_pass: false,
+            window: None,
+        });
+    }
+
+    // Moves whole files left, highest id first, into the free span picked by
+    // `strategy.fit`. Plain first fit runs on the disk's free list, anything
+    // else on a `FreeIndex` built from it.
+    pub fn compact_files_with(&mut self, strategy: Strategy) -> Stats {
+        let mut stats = Stats::default();
+        if strategy.fit == Fit::First && !strategy.multi_pass && strategy.window.is_none() {
+            let mut free = std::mem::replace(&mut self.free, FreeList::new());
+            self.move_files(&mut free, false, &mut stats);
+            self.free = free;
+        } else {
+            let mut free = FreeIndex::new(strategy.fit, strategy.window);
+            for span in self.free.spans() {
+                free.push(span);
+            }
+            self.move_files(&mut free, strategy.multi_pass, &mut stats);
+            self.free = FreeList::new();
+            for span in free.spans() {
+                self.free.push(span);
+            }
+        }
+        self.update_stats(&mut stats);
+        return stats;
+    }
+
+    fn move_files<F: FreeSpace>(&mut self, free: &mut F, multi_pass: bool, stats: &mut Stats) {
+        loop {
+            stats.passes += 1;
+            let mut moved = false;
+            for id in (0..self.files.len()).rev() {
+                let file = self.files[id][0];
+                if file.len == 0 {
+                    continue;
+                }
+                if let Some(span) = free.take_for(file) {
+                    self.files[id][0].start = span.start;
+                    free.give_back(Span {
+                        start: span.start + file.len,
+                        len: span.len - file.len,
+                    });
+                    free.give_back(file);
+                    self.trace.push(Move {
+                        id: id,
+                        from: file,
+                        to: self.files[id][0],
+                    });
+                    stats.moves += 1;
+                    moved = true;
+                }
+            }
+            if !multi_pass || !moved {
+                break;
+            }
+        }
+    }
+
+    // Fills in the layout parts of `stats`. Touching free spans are counted
+    // as one, as the free list doesn't merge them.
+    pub fn update_stats(&self, stats: &mut Stats) {
+        let data_end = self
+            .files
+            .iter()
+            .flat_map(|spans| spans.iter())
+            .map(|s| s.start + s.len)
+            .max()
+            .unwrap_or(0);
+        let mut merged: Vec<Span> = Vec::new();
+        for span in self.free.spans() {
+            if span.start >= data_end {
+                break;
+            }
+            match merged.last_mut() {
+                Some(last) if last.start + last.len == span.start => last.len += span.len,
+                _ => merged.push(span),
+            }
+        }
+        stats.free_spans = merged.len();
+        stats.free_blocks = merged.iter().map(|s| s.len).sum();
+        stats.largest_free_span = merged.iter().map(|s| s.len).max().unwrap_or(0);
+    }
+
+    pub fn checksum(&self) -> usize {
+        let mut checksum = 0;
+        for (id, spans) in self.files.iter().enumerate() {
+            for span in spans {
+                checksum += id * (span.start..span.start + span.len).sum::<usize>();
+            }
+        }
+        return checksum;
+    }
+
+    // File id of each block, None for free blocks.
+    pub fn layout(&self) -> Vec<Option<usize>> {
+        let mut layout = vec![None; self.size];
+        for (id, spans) in self.files.iter().enumerate() {
+            for span in spans {
+                for block in span.start..span.start + span.len {
+                    layout[block] = Some(id);
+                }
+            }
+        }
+        return layout;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    // Each block padded to the widest id and followed by '|'.
+    Delimited,
+    // Each block padded to the widest id and followed by a space, with the
+    // color alternating from one run of blocks to the next so neighbouring
+    // files stand apart.
+    Colored,
+}
+
+impl Style {
+    pub fn parse(name: &str) -> Option<Style> {
+        match name {
+            "delimited" => Some(Style::Delimited),
+            "colored" => Some(Style::Colored),
+            _ => None,
+        }
+    }
+
+    fn width(&self, layouts: &[&Vec<Option<usize>>]) -> usize {
+        let max_id = layouts
+            .iter()
+            .flat_map(|layout| layout.iter())
+            .filter_map(|block| *block)
+            .max()
+            .unwrap_or(0);
+        return max_id.to_string().len();
+    }
+
+    // `run` counts the runs of file blocks before this one.
+    fn cell(&self, block: Option<usize>, width: usize, run: usize) -> String {
+        match (self, block) {
+            (&Style::Delimited, Some(id)) => format!("{:>1$}|", id, width),
+            (&Style::Delimited, None) => format!("{:>1$}|", ".", width),
+            (&Style::Colored, Some(id)) => {
+                let color = if run % 2 == 0 { 31 } else { 34 };
+                format!("\x1b[{}m{:>2$}\x1b[0m ", color, id, width)
+            }
+            (&Style::Colored, None) => format!("{:>1$} ", ".", width),
+        }
+    }
+
+    fn line(&self, layout: &Vec<Option<usize>>, width: usize) -> String {
+        let mut line = String::new();
+        let mut run = 0;
+        for (i, block) in layout.iter().enumerate() {
+            if i > 0 && block.is_some() && *block != layout[i - 1] {
+                run += 1;
+            }
+            line += &self.cell(*block, width, run);
+        }
+        return line;
+    }
+
+    // Cells line up with `cell`, so markers can be printed under a layout.
+    fn marker(&self, changed: bool, width: usize) -> String {
+        let c = if changed { "^" } else { " " };
+        return c.repeat(width + 1);
+    }
+}
+
+pub fn render(layout: &Vec<Option<usize>>, style: Style) -> String {
+    return style.line(layout, style.width(&[layout]));
+}
+
+// Both layouts, one above the other, with the blocks that differ marked
+// below them.
+pub fn render_diff(
+    before: &Vec<Option<usize>>,
+    after: &Vec<Option<usize>>,
+    style: Style,
+) -> String {
+    let width = style.width(&[before, after]);
+    let mut changed = 0;
+    let mut markers = String::new();
+    for (a, b) in before.iter().zip(after.iter()) {
+        changed += (a != b) as usize;
+        markers += &style.marker(a != b, width);
+    }
+    return format!(
+        "{}\n{}\n{}\n{} blocks changed\n",
+        style.line(before, width),
+        style.line(after, width),
+        markers.trim_end(),
+        changed
+    );
+}
+
+// Replays a move from `Disk::trace` on a layout.
+pub fn apply(layout: &mut Vec<Option<usize>>, step: &Move) {
+    for block in step.from.start..step.from.start + step.from.len {
+        layout[block] = None;
+    }
+    for block in step.to.start..step.to.start + step.to.len {
+        layout[block] = Some(step.id);
+    }
+}
+
+// Prints the moves recorded in `disk` as a numbered step list if `steps`, each
+// followed by the layout after it if there is a `style`, then the diff between
+// `initial` and the final layout.
+pub fn print_trace(initial: &Vec<Option<usize>>, disk: &Disk, style: Option<Style>, steps: bool) {
+    let mut layout = initial.clone();
+    if steps {
+        for (i, step) in disk.trace.iter().enumerate() {
+            println!("{}. {}", i + 1, step);
+            if let Some(style) = style {
+                apply(&mut layout, step);
+                println!("{}", render(&layout, style));
+            }
+        }
+    }
+    if let Some(style) = style {
+        print!("{}", render_diff(initial, &disk.layout(), style));
+    }
+}
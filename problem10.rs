@@ -1,82 +1,314 @@
-extern crate pathfinding;
+// After cargo build --release, build with rustc -L target/release/deps problem10.rs
 
-use pathfinding::prelude;
+extern crate num_bigint;
+
+use num_bigint::BigUint;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-struct Point {
-    x: usize,
-    y: usize,
-    v: u8,
+// Trails go up by 1 to STEP_UP, optionally diagonally, from START_HEIGHT to
+// END_HEIGHT. 1, false, 0 and 9 are the puzzle's rules.
+const STEP_UP: u8 = 1;
+const DIAGONALS: bool = false;
+const START_HEIGHT: u8 = 0;
+const END_HEIGHT: u8 = 9;
+// Print every trail from this trailhead, given as "x,y", or from all of them
+// with "all".
+const TRAILHEAD: &str = "";
+// Print how many distinct trails pass through each cell as CSV, counting
+// only trails from TRAILHEAD if it is set.
+const TRAFFIC_CSV: bool = false;
+
+struct TrailRules {
+    // Largest height gain allowed between neighbours, the smallest is 1.
+    max_step: u8,
+    diagonals: bool,
+    start: u8,
+    end: u8,
+}
+
+struct Grid {
+    width: usize,
+    height: usize,
+    // Height of each cell row by row, None for impassable '.' cells.
+    cells: Vec<Option<u8>>,
 }
 
-// Implement necessary traits for pathfinding crate
-impl Point {
-    fn successors(&self, graph: &Vec<Self>) -> Vec<Self> {
-        let current_index = graph.iter().position(|p| p == self).unwrap();
+impl Grid {
+    // Reads rows of digits like the puzzle, or rows of comma or space
+    // separated heights up to 255.
+    fn from_file(file_name: &str) -> Grid {
+        let file = File::open(file_name).expect("Unable to open file");
+        let reader = BufReader::new(file);
+        let mut grid = Grid {
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+        };
+        for line in reader.lines() {
+            let line = line.expect("Unable to read line");
+            let row: Vec<Option<u8>> = if line.contains(|c| c == ',' || c == ' ') {
+                line.split(|c| c == ',' || c == ' ')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.parse().ok())
+                    .collect()
+            } else {
+                line.chars()
+                    .map(|c| c.to_digit(10).map(|v| v as u8))
+                    .collect()
+            };
+            grid.width = row.len();
+            grid.cells.extend(row);
+            grid.height += 1;
+        }
+        return grid;
+    }
+
+    // Neighbouring cells `index` can step up to.
+    fn successors(&self, index: usize, rules: &TrailRules) -> Vec<usize> {
+        let v = match self.cells[index] {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        let (x, y) = (index % self.width, index / self.width);
         let mut successors = Vec::new();
-        for (index, point) in graph.iter().enumerate() {
-            if index != current_index
-                && point.v == self.v + 1
-                && ((point.x == self.x && (point.y == self.y + 1 || point.y == self.y - 1))
-                    || (point.y == self.y && (point.x == self.x + 1 || point.x == self.x - 1)))
-            {
-                successors.push(point.clone());
+        let neighbours = [
+            (0, -1),
+            (1, 0),
+            (0, 1),
+            (-1, 0),
+            (1, -1),
+            (1, 1),
+            (-1, 1),
+            (-1, -1),
+        ];
+        let count = if rules.diagonals { 8 } else { 4 };
+        for &(dx, dy) in neighbours[..count].iter() {
+            let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+            if nx < 0 || ny < 0 || nx >= self.width as i64 || ny >= self.height as i64 {
+                continue;
+            }
+            let next = ny as usize * self.width + nx as usize;
+            if let Some(next_v) = self.cells[next] {
+                if next_v > v && next_v - v <= rules.max_step {
+                    successors.push(next);
+                }
+            }
+        }
+        return successors;
+    }
+
+    // Cell indices grouped by height, lowest first.
+    fn layers(&self) -> Vec<Vec<usize>> {
+        let mut layers = vec![Vec::new(); 256];
+        for (index, cell) in self.cells.iter().enumerate() {
+            if let Some(v) = *cell {
+                layers[v as usize].push(index);
             }
         }
-        successors
+        return layers;
     }
 }
 
-fn main() {
-    let file = File::open("input10.txt").expect("Unable to open file");
-    let reader = BufReader::new(file);
-    let mut input = Vec::new();
-    let mut start_points = Vec::new();
-    let mut end_points = Vec::new();
-    let mut extents = Point { x: 0, y: 0, v: 0 };
-
-    for line in reader.lines() {
-        let line = line.expect("Unable to read line");
-        extents.x = line.len();
-        for (x, c) in line.chars().enumerate() {
-            match c {
-                '.' => {}
-                c => {
-                    input.push(Point {
-                        x: x,
-                        y: extents.y,
-                        v: c.to_digit(10).expect("fail") as u8,
-                    });
-                    if c == '9' {
-                        end_points.push(input.last().expect("fail").clone());
-                    } else if c == '0' {
-                        start_points.push(input.last().expect("fail").clone());
-                    }
+// The score (distinct summits reachable) and rating (distinct trails to any
+// summit) of every cell between the start and end heights. Trails only go
+// up, so both are filled in a height layer at a time from the summits down,
+// each cell combining its successors. Summit sets are only kept for the
+// layers a step can come from. Ratings are big integers, as wide step ranges,
+// diagonals and tall maps easily make more than 2^128 trails.
+fn scores_and_ratings(grid: &Grid, rules: &TrailRules) -> (Vec<usize>, Vec<BigUint>) {
+    let mut scores = vec![0; grid.cells.len()];
+    let mut ratings: Vec<BigUint> = vec![BigUint::default(); grid.cells.len()];
+    let mut summits: Vec<HashSet<usize>> = vec![HashSet::new(); grid.cells.len()];
+    let layers = grid.layers();
+    for v in (rules.start as usize..=rules.end as usize).rev() {
+        for &index in &layers[v] {
+            if v == rules.end as usize {
+                ratings[index] = BigUint::from(1_u32);
+                summits[index].insert(index);
+            } else {
+                let mut reached = HashSet::new();
+                for next in grid.successors(index, rules) {
+                    ratings[index] = &ratings[index] + &ratings[next];
+                    reached.extend(summits[next].iter().cloned());
                 }
+                summits[index] = reached;
+            }
+            scores[index] = summits[index].len();
+        }
+        // Nothing below this layer can step up to the one `max_step` above.
+        let done = v + rules.max_step as usize;
+        if done <= rules.end as usize {
+            for &index in &layers[done] {
+                summits[index] = HashSet::new();
             }
         }
-        extents.y += 1;
     }
+    return (scores, ratings);
+}
 
-    let mut total_path_count: usize = 0;
-    let mut total_score: usize = 0;
-    for start_node in &start_points {
-        for reachable_node in prelude::dfs_reach(start_node.clone(), |p| p.successors(&input)) {
-            if reachable_node.v == 9 {
-                total_score += 1;
-                let path_count = prelude::count_paths(
-                    start_node.clone(),
-                    |p| p.successors(&input),
-                    |p| *p == reachable_node,
-                );
-                total_path_count += path_count;
+// Every trail from `start` as the cells along it. Only steps onto cells with
+// a rating, so no time is spent in dead ends.
+fn trails(
+    grid: &Grid,
+    rules: &TrailRules,
+    ratings: &Vec<BigUint>,
+    start: usize,
+) -> Vec<Vec<usize>> {
+    let mut trails = Vec::new();
+    if grid.cells[start] == Some(rules.start) && ratings[start] != BigUint::default() {
+        extend_trails(grid, rules, ratings, &mut vec![start], &mut trails);
+    }
+    return trails;
+}
+
+fn extend_trails(
+    grid: &Grid,
+    rules: &TrailRules,
+    ratings: &Vec<BigUint>,
+    path: &mut Vec<usize>,
+    trails: &mut Vec<Vec<usize>>,
+) {
+    let last = *path.last().unwrap();
+    if grid.cells[last] == Some(rules.end) {
+        trails.push(path.clone());
+        return;
+    }
+    for next in grid.successors(last, rules) {
+        if ratings[next] != BigUint::default() {
+            path.push(next);
+            extend_trails(grid, rules, ratings, path, trails);
+            path.pop();
+        }
+    }
+}
+
+// How many distinct trails from `starts` pass through each cell: the trails
+// arriving at a cell times the trails leaving it, which is its rating.
+// Arrivals are pushed up a height layer at a time, like the ratings are
+// pulled down.
+fn traffic(
+    grid: &Grid,
+    rules: &TrailRules,
+    ratings: &Vec<BigUint>,
+    starts: &Vec<usize>,
+) -> Vec<BigUint> {
+    let zero = BigUint::default();
+    let mut arrivals: Vec<BigUint> = vec![zero.clone(); grid.cells.len()];
+    for &start in starts {
+        arrivals[start] = BigUint::from(1_u32);
+    }
+    let layers = grid.layers();
+    for v in rules.start as usize..rules.end as usize {
+        for &index in &layers[v] {
+            if arrivals[index] == zero {
+                continue;
+            }
+            for next in grid.successors(index, rules) {
+                arrivals[next] = &arrivals[next] + &arrivals[index];
             }
         }
     }
+    return arrivals
+        .iter()
+        .zip(ratings.iter())
+        .map(|(a, r)| a * r)
+        .collect();
+}
+
+fn to_csv(grid: &Grid, values: &Vec<BigUint>) -> String {
+    let mut csv = String::new();
+    for row in values.chunks(grid.width) {
+        let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+        csv += &(row.join(",") + "\n");
+    }
+    return csv;
+}
+
+// The trailheads named by `spec`: none for "", every one for "all", or the
+// one at "x,y", which has to be on the map at the start height.
+fn chosen_trailheads(grid: &Grid, rules: &TrailRules, spec: &str) -> Result<Vec<usize>, String> {
+    match spec {
+        "" => return Ok(Vec::new()),
+        "all" => return Ok(grid.layers()[rules.start as usize].clone()),
+        _ => {}
+    }
+    let coords: Vec<usize> = spec
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| format!("Trailhead '{}' isn't of the form x,y", spec))?;
+    if coords.len() != 2 {
+        return Err(format!("Trailhead '{}' isn't of the form x,y", spec));
+    }
+    let (x, y) = (coords[0], coords[1]);
+    if x >= grid.width || y >= grid.height {
+        return Err(format!(
+            "Trailhead ({}, {}) is outside the {}x{} map",
+            x, y, grid.width, grid.height
+        ));
+    }
+    let index = y * grid.width + x;
+    if grid.cells[index] != Some(rules.start) {
+        return Err(format!(
+            "({}, {}) isn't a trailhead, its height is {} rather than {}",
+            x,
+            y,
+            grid.cells[index].map_or(String::from("impassable"), |v| v.to_string()),
+            rules.start
+        ));
+    }
+    return Ok(vec![index]);
+}
+
+fn main() {
+    let grid = Grid::from_file("input10.txt");
+    // let grid = Grid::from_file("input10-test.txt");
+
+    let rules = TrailRules {
+        max_step: STEP_UP,
+        diagonals: DIAGONALS,
+        start: START_HEIGHT,
+        end: END_HEIGHT,
+    };
+
+    let (scores, ratings) = scores_and_ratings(&grid, &rules);
+    let mut total_path_count = BigUint::default();
+    let mut total_score: usize = 0;
+    for &start in &grid.layers()[rules.start as usize] {
+        total_score += scores[start];
+        total_path_count += &ratings[start];
+    }
     println!(
         "All paths: total_score = {}, total_path_count={}",
         total_score, total_path_count
     );
+
+    let starts = match chosen_trailheads(&grid, &rules, TRAILHEAD) {
+        Ok(starts) => starts,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+    for &start in &starts {
+        let xy = |index: usize| (index % grid.width, index / grid.width);
+        for (i, trail) in trails(&grid, &rules, &ratings, start).iter().enumerate() {
+            let cells: Vec<(usize, usize)> = trail.iter().map(|c| xy(*c)).collect();
+            println!("Trail {} from {:?}: {:?}", i + 1, xy(start), cells);
+        }
+    }
+
+    if TRAFFIC_CSV {
+        let from = if starts.is_empty() {
+            grid.layers()[rules.start as usize].clone()
+        } else {
+            starts
+        };
+        print!(
+            "{}",
+            to_csv(&grid, &traffic(&grid, &rules, &ratings, &from))
+        );
+    }
 }
@@ -1,4 +1,89 @@
+// After cargo build --release, build with rustc -L target/release/deps problem11.rs
+
+extern crate num_bigint;
+
+use num_bigint::BigUint;
 use std::collections::HashMap;
+use std::fmt::Display;
+
+// Each blink a stone is replaced by the outputs of the first rule whose
+// predicate matches it, and stays as it is if none do. Rules are separated by
+// ';' and outputs by ',':
+//
+//   predicates: N (equals N), even_digits, odd_digits, div K, any
+//   outputs:    N, split (halves of the digits), mul K, add K, keep
+const RULES: &str = "0 -> 1; even_digits -> split; any -> mul 2024";
+// "memo" counts each input stone recursively with a cache per depth. "u128"
+// and "bigint" count stones per distinct value a blink at a time instead,
+// which keeps going for as many blinks as the totals fit in.
+const BACKEND: &str = "memo";
+// With "u128" or "bigint", Some(n) prints the number of distinct values and
+// the n most common ones after each blink.
+const DISTRIBUTION_TOP: Option<usize> = None;
+// Print the set of values reachable from the input stones and the stone
+// count after HUGE_BLINKS blinks modulo MODULUS.
+const ANALYZE_CLOSURE: bool = false;
+const HUGE_BLINKS: u64 = 1_000_000_000_000;
+const MODULUS: u64 = 1_000_000_007;
+// Give up on the closure past this many values, some rule sets never close.
+const MAX_CLOSURE: usize = 100_000;
+// Closures up to this size raise the transition matrix to the power
+// directly. Larger ones take the recurrence route, see `count_mod`.
+const DENSE_LIMIT: usize = 300;
+
+// Stone totals for the per value counting.
+trait Count: Clone + Ord + Display {
+    fn one() -> Self;
+    fn plus(&self, other: &Self) -> Self;
+}
+
+impl Count for u128 {
+    fn one() -> Self {
+        return 1;
+    }
+    fn plus(&self, other: &Self) -> Self {
+        return self
+            .checked_add(*other)
+            .expect("Stone count overflowed u128, use the bigint backend");
+    }
+}
+
+impl Count for BigUint {
+    fn one() -> Self {
+        return BigUint::from(1_u32);
+    }
+    fn plus(&self, other: &Self) -> Self {
+        return self + other;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Predicate {
+    Equals(u64),
+    EvenDigits,
+    OddDigits,
+    DivisibleBy(u64),
+    Any,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    Value(u64),
+    Split,
+    Multiply(u64),
+    Add(u64),
+    Keep,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    predicate: Predicate,
+    outputs: Vec<Output>,
+}
+
+fn num_digits(v: u64) -> u32 {
+    return if v == 0 { 1 } else { v.ilog10() + 1 };
+}
 
 // Turns 1234 -> 12, 34, etc.
 fn split_number(v: u64, num_digits: u32) -> (u64, u64) {
@@ -8,8 +93,118 @@ fn split_number(v: u64, num_digits: u32) -> (u64, u64) {
     return (first_part, second_part);
 }
 
+impl Predicate {
+    fn parse(s: &str) -> Result<Predicate, String> {
+        let s = s.trim();
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let number = |w: &str| {
+            w.parse::<u64>()
+                .map_err(|_| format!("Bad number in '{}'", s))
+        };
+        return match words.as_slice() {
+            ["even_digits"] => Ok(Predicate::EvenDigits),
+            ["odd_digits"] => Ok(Predicate::OddDigits),
+            ["any"] => Ok(Predicate::Any),
+            ["div", k] => match number(k)? {
+                0 => Err(format!("Can't divide by 0 in '{}'", s)),
+                k => Ok(Predicate::DivisibleBy(k)),
+            },
+            [n] => Ok(Predicate::Equals(number(n)?)),
+            _ => Err(format!("Unknown predicate '{}'", s)),
+        };
+    }
+
+    fn matches(&self, v: u64) -> bool {
+        match *self {
+            Predicate::Equals(n) => v == n,
+            Predicate::EvenDigits => num_digits(v) % 2 == 0,
+            Predicate::OddDigits => num_digits(v) % 2 == 1,
+            Predicate::DivisibleBy(k) => v % k == 0,
+            Predicate::Any => true,
+        }
+    }
+}
+
+impl Output {
+    fn parse(s: &str) -> Result<Output, String> {
+        let s = s.trim();
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let number = |w: &str| {
+            w.parse::<u64>()
+                .map_err(|_| format!("Bad number in '{}'", s))
+        };
+        return match words.as_slice() {
+            ["split"] => Ok(Output::Split),
+            ["keep"] => Ok(Output::Keep),
+            ["mul", k] => Ok(Output::Multiply(number(k)?)),
+            ["add", k] => Ok(Output::Add(number(k)?)),
+            [n] => Ok(Output::Value(number(n)?)),
+            _ => Err(format!("Unknown output '{}'", s)),
+        };
+    }
+
+    fn apply(&self, v: u64, stones: &mut Vec<u64>) -> Result<(), String> {
+        let overflow = || format!("Stone value {} overflowed applying {:?}", v, self);
+        match *self {
+            Output::Value(n) => stones.push(n),
+            Output::Split => {
+                let (first, second) = split_number(v, num_digits(v));
+                stones.push(first);
+                stones.push(second);
+            }
+            Output::Multiply(k) => stones.push(v.checked_mul(k).ok_or_else(overflow)?),
+            Output::Add(k) => stones.push(v.checked_add(k).ok_or_else(overflow)?),
+            Output::Keep => stones.push(v),
+        }
+        return Ok(());
+    }
+}
+
+fn parse_rules(description: &str) -> Result<Vec<Rule>, String> {
+    let mut rules = Vec::new();
+    for rule in description.split(';').filter(|r| !r.trim().is_empty()) {
+        let (predicate, outputs) = rule
+            .split_once("->")
+            .ok_or(format!("Missing '->' in rule '{}'", rule.trim()))?;
+        rules.push(Rule {
+            predicate: Predicate::parse(predicate)?,
+            outputs: outputs
+                .split(',')
+                .map(Output::parse)
+                .collect::<Result<Vec<Output>, String>>()?,
+        });
+    }
+    return Ok(rules);
+}
+
+// The stones `v` turns into after one blink, or an error if one of them
+// doesn't fit a u64.
+fn blink(v: u64, rules: &Vec<Rule>) -> Result<Vec<u64>, String> {
+    let mut stones = Vec::new();
+    match rules.iter().find(|r| r.predicate.matches(v)) {
+        Some(rule) => {
+            for output in &rule.outputs {
+                output.apply(v, &mut stones)?;
+            }
+        }
+        None => stones.push(v),
+    }
+    return Ok(stones);
+}
+
+// `blink` for the counting paths, which have no way to report errors.
+fn blink_or_panic(v: u64, rules: &Vec<Rule>) -> Vec<u64> {
+    return blink(v, rules).unwrap_or_else(|error| panic!("{}", error));
+}
+
 // Splits stones according to the rules. Sticks result in `cache`
-fn split_stone(v: u64, d: u8, max_d: u8, mut cache: &mut HashMap<(u8, u64), u64>) -> u64 {
+fn split_stone(
+    v: u64,
+    d: u32,
+    max_d: u32,
+    rules: &Vec<Rule>,
+    mut cache: &mut HashMap<(u32, u64), u64>,
+) -> u64 {
     if d >= max_d {
         return 1;
     }
@@ -22,39 +217,398 @@ fn split_stone(v: u64, d: u8, max_d: u8, mut cache: &mut HashMap<(u8, u64), u64>
         }
     }
 
-    let result: u64;
-    if v == 0 {
-        result = split_stone(1, d + 1, max_d, &mut cache);
-    } else {
-        let num_digits = v.ilog10() + 1;
-        if num_digits & 1 == 0 {
-            let (first_four, last_four) = split_number(v, num_digits);
-            result = split_stone(first_four, d + 1, max_d, &mut cache)
-                + split_stone(last_four, d + 1, max_d, &mut cache);
+    let mut result: u64 = 0;
+    for next in blink_or_panic(v, rules) {
+        result += split_stone(next, d + 1, max_d, rules, &mut cache);
+    }
+
+    cache.insert((d, v), result);
+    return result;
+}
+
+// One blink over stones grouped by value: every stone of a value turns into
+// the same stones, so each value only has to be looked at once.
+fn blink_counts<C: Count>(stones: &HashMap<u64, C>, rules: &Vec<Rule>) -> HashMap<u64, C> {
+    let mut next_stones: HashMap<u64, C> = HashMap::new();
+    for (v, count) in stones {
+        for next in blink_or_panic(*v, rules) {
+            let total = match next_stones.get(&next) {
+                Some(total) => total.plus(count),
+                None => count.clone(),
+            };
+            next_stones.insert(next, total);
+        }
+    }
+    return next_stones;
+}
+
+fn print_distribution<C: Count>(blink: u32, stones: &HashMap<u64, C>, top: usize) {
+    let mut by_count: Vec<(&u64, &C)> = stones.iter().collect();
+    by_count.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let top: Vec<String> = by_count
+        .iter()
+        .take(top)
+        .map(|(v, count)| format!("{}x{}", v, count))
+        .collect();
+    println!(
+        "blink={}, distinct_values={}, top: {}",
+        blink,
+        stones.len(),
+        top.join(" ")
+    );
+}
+
+fn count_by_value<C: Count>(stones: &Vec<u64>, blinks: u32, rules: &Vec<Rule>) {
+    let mut counts: HashMap<u64, C> = HashMap::new();
+    for v in stones {
+        let total = match counts.get(v) {
+            Some(total) => total.plus(&C::one()),
+            None => C::one(),
+        };
+        counts.insert(*v, total);
+    }
+    for blink in 1..=blinks {
+        counts = blink_counts(&counts, rules);
+        if let Some(top) = DISTRIBUTION_TOP {
+            print_distribution(blink, &counts, top);
+        }
+    }
+    let total = counts
+        .values()
+        .fold(None, |total: Option<C>, count| match total {
+            Some(total) => Some(total.plus(count)),
+            None => Some(count.clone()),
+        });
+    println!(
+        "stones={}, distinct_values={}",
+        total.map_or(String::from("0"), |t| t.to_string()),
+        counts.len()
+    );
+}
+
+// The values reachable from some stones under the rules, with what each turns
+// into in one blink.
+struct Closure {
+    values: Vec<u64>,
+    index: HashMap<u64, usize>,
+    // For each value, the indices of the values it turns into, with repeats.
+    transitions: Vec<Vec<usize>>,
+}
+
+fn closure(stones: &Vec<u64>, rules: &Vec<Rule>) -> Result<Closure, String> {
+    let mut closure = Closure {
+        values: Vec::new(),
+        index: HashMap::new(),
+        transitions: Vec::new(),
+    };
+    let add = |v: u64, closure: &mut Closure| -> usize {
+        if let Some(i) = closure.index.get(&v) {
+            return *i;
+        }
+        closure.index.insert(v, closure.values.len());
+        closure.values.push(v);
+        return closure.values.len() - 1;
+    };
+    for v in stones {
+        add(*v, &mut closure);
+    }
+    // Values are appended as they're found, so this is a breadth first search.
+    let mut i = 0;
+    while i < closure.values.len() {
+        if closure.values.len() > MAX_CLOSURE {
+            return Err(format!("More than {} reachable values", MAX_CLOSURE));
+        }
+        let next: Vec<usize> = blink(closure.values[i], rules)?
+            .into_iter()
+            .map(|v| add(v, &mut closure))
+            .collect();
+        closure.transitions.push(next);
+        i += 1;
+    }
+    return Ok(closure);
+}
+
+// Square matrix with entries modulo MODULUS.
+struct Matrix {
+    n: usize,
+    cells: Vec<u64>,
+}
+
+impl Matrix {
+    fn identity(n: usize) -> Matrix {
+        let mut cells = vec![0; n * n];
+        for i in 0..n {
+            cells[i * n + i] = 1;
+        }
+        return Matrix { n: n, cells: cells };
+    }
+
+    // Entry (i, j) is how many stones of value j a stone of value i turns into.
+    fn from_closure(closure: &Closure) -> Matrix {
+        let n = closure.values.len();
+        let mut matrix = Matrix {
+            n: n,
+            cells: vec![0; n * n],
+        };
+        for (i, next) in closure.transitions.iter().enumerate() {
+            for j in next {
+                matrix.cells[i * n + j] += 1;
+            }
+        }
+        return matrix;
+    }
+
+    fn mul(&self, other: &Matrix) -> Matrix {
+        let n = self.n;
+        let mut cells = vec![0; n * n];
+        for i in 0..n {
+            for k in 0..n {
+                let a = self.cells[i * n + k];
+                if a == 0 {
+                    continue;
+                }
+                for j in 0..n {
+                    cells[i * n + j] = (cells[i * n + j] + a * other.cells[k * n + j]) % MODULUS;
+                }
+            }
+        }
+        return Matrix { n: n, cells: cells };
+    }
+
+    fn pow(&self, mut exponent: u64) -> Matrix {
+        let mut result = Matrix::identity(self.n);
+        let mut base = Matrix {
+            n: self.n,
+            cells: self.cells.clone(),
+        };
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.mul(&base);
+            exponent >>= 1;
+        }
+        return result;
+    }
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64) -> u64 {
+    let mut result = 1;
+    base %= MODULUS;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % MODULUS;
+        }
+        base = base * base % MODULUS;
+        exponent >>= 1;
+    }
+    return result;
+}
+
+// Shortest recurrence s[k] = c[0] s[k - 1] + c[1] s[k - 2] + ... that
+// generates `s` modulo MODULUS (Berlekamp-Massey).
+fn find_recurrence(s: &Vec<u64>) -> Vec<u64> {
+    let (mut c, mut b) = (vec![1_u64], vec![1_u64]);
+    let (mut length, mut shift, mut last_discrepancy) = (0, 1, 1);
+    for k in 0..s.len() {
+        let mut d = s[k];
+        for i in 1..=length {
+            d = (d + c[i] * s[k - i]) % MODULUS;
+        }
+        if d == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = d * pow_mod(last_discrepancy, MODULUS - 2) % MODULUS;
+        let previous = c.clone();
+        if c.len() < b.len() + shift {
+            c.resize(b.len() + shift, 0);
+        }
+        for i in 0..b.len() {
+            c[i + shift] = (c[i + shift] + MODULUS - scale * b[i] % MODULUS) % MODULUS;
+        }
+        if 2 * length <= k {
+            length = k + 1 - length;
+            b = previous;
+            last_discrepancy = d;
+            shift = 1;
         } else {
-            result = split_stone(v * 2024, d + 1, max_d, &mut cache);
+            shift += 1;
         }
     }
+    c.resize(length + 1, 0);
+    return c[1..].iter().map(|x| (MODULUS - x) % MODULUS).collect();
+}
 
-    cache.insert((d, v), result);
+// x^n modulo the recurrence's characteristic polynomial, as coefficients of
+// x^0 .. x^(len - 1). These weight the first terms of the sequence into s[n].
+fn power_mod_recurrence(recurrence: &Vec<u64>, mut n: u64) -> Vec<u64> {
+    let len = recurrence.len();
+    let mul = |a: &Vec<u64>, b: &Vec<u64>| -> Vec<u64> {
+        let mut product = vec![0; 2 * len];
+        for i in 0..len {
+            if a[i] == 0 {
+                continue;
+            }
+            for j in 0..len {
+                product[i + j] = (product[i + j] + a[i] * b[j]) % MODULUS;
+            }
+        }
+        // Fold x^k for k >= len back down using the recurrence.
+        for k in (len..2 * len).rev() {
+            let top = product[k];
+            if top == 0 {
+                continue;
+            }
+            for i in 0..len {
+                product[k - 1 - i] = (product[k - 1 - i] + top * recurrence[i]) % MODULUS;
+            }
+        }
+        product.truncate(len);
+        return product;
+    };
+    let mut result = vec![0; len];
+    let mut base = vec![0; len];
+    result[0] = 1;
+    if len == 1 {
+        base[0] = recurrence[0];
+    } else {
+        base[1] = 1;
+    }
+    while n > 0 {
+        if n & 1 == 1 {
+            result = mul(&result, &base);
+        }
+        base = mul(&base, &base);
+        n >>= 1;
+    }
     return result;
 }
 
+// The number of stones after `blinks` blinks, modulo MODULUS.
+//
+// For small closures this sums the input stones' rows of the transition
+// matrix raised to `blinks`. Dense powers cost O(n^3) per product though,
+// hours for the puzzle's closure of a few thousand values. The totals
+// s[k] = x M^k 1 for input counts x satisfy a linear recurrence of order at
+// most n, so past DENSE_LIMIT the first 2n totals are found with sparse
+// blinks, the recurrence is recovered from them, and s[blinks] follows from
+// raising x to `blinks` modulo its characteristic polynomial, which is the
+// same exponentiation done with the recurrence's companion matrix.
+fn count_mod(closure: &Closure, stones: &Vec<u64>, blinks: u64) -> (u64, Option<usize>) {
+    let n = closure.values.len();
+    let inputs: Vec<usize> = stones.iter().map(|v| closure.index[v]).collect();
+    if n <= DENSE_LIMIT {
+        let power = Matrix::from_closure(closure).pow(blinks);
+        let mut total = 0;
+        for i in inputs {
+            total = power.cells[i * n..(i + 1) * n]
+                .iter()
+                .fold(total, |t, c| (t + c) % MODULUS);
+        }
+        return (total, None);
+    }
+
+    // counts[i] is how many stones a value i stone turns into after k blinks.
+    let mut counts = vec![1_u64; n];
+    let mut totals = Vec::new();
+    for k in 0..2 * n as u64 {
+        if k == blinks {
+            break;
+        }
+        totals.push(inputs.iter().fold(0, |t, i| (t + counts[*i]) % MODULUS));
+        counts = closure
+            .transitions
+            .iter()
+            .map(|next| next.iter().fold(0, |t, j| (t + counts[*j]) % MODULUS))
+            .collect();
+    }
+    if blinks <= totals.len() as u64 {
+        let total = inputs.iter().fold(0, |t, i| (t + counts[*i]) % MODULUS);
+        return (total, None);
+    }
+    let recurrence = find_recurrence(&totals);
+    if recurrence.is_empty() {
+        return (0, Some(0));
+    }
+    let weights = power_mod_recurrence(&recurrence, blinks);
+    let total = weights
+        .iter()
+        .zip(totals.iter())
+        .fold(0, |t, (w, s)| (t + w * s) % MODULUS);
+    return (total, Some(recurrence.len()));
+}
+
+fn analyze_closure(stones: &Vec<u64>, rules: &Vec<Rule>) {
+    let closure = match closure(stones, rules) {
+        Ok(closure) => closure,
+        Err(error) => {
+            eprintln!("Can't analyze closure: {}", error);
+            return;
+        }
+    };
+    let transitions: usize = closure.transitions.iter().map(|t| t.len()).sum();
+    let distinct: usize = closure
+        .transitions
+        .iter()
+        .map(|t| {
+            let mut t = t.clone();
+            t.sort();
+            t.dedup();
+            t.len()
+        })
+        .sum();
+    println!(
+        "closure: values={}, transitions={}, matrix_entries={}, max_value={}",
+        closure.values.len(),
+        transitions,
+        distinct,
+        closure.values.iter().max().unwrap_or(&0)
+    );
+    let (total, order) = count_mod(&closure, stones, HUGE_BLINKS);
+    if let Some(order) = order {
+        println!("recurrence_order={}", order);
+    }
+    println!(
+        "stones after {} blinks mod {} = {}",
+        HUGE_BLINKS, MODULUS, total
+    );
+}
+
 fn main() {
-    const BLINKS: u8 = 75; // 25 for part 1.
+    const BLINKS: u32 = 75; // 25 for part 1.
 
     // let stones: Vec<u64> = vec![125, 17];
     let stones: Vec<u64> = vec![5688, 62084, 2, 3248809, 179, 79, 0, 172169];
 
+    let rules = match parse_rules(RULES) {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+
     for v in &stones {
         print!("{} ", v)
     }
     println!();
 
+    if ANALYZE_CLOSURE {
+        analyze_closure(&stones, &rules);
+    }
+
+    match BACKEND {
+        "memo" => {}
+        "u128" => return count_by_value::<u128>(&stones, BLINKS, &rules),
+        "bigint" => return count_by_value::<BigUint>(&stones, BLINKS, &rules),
+        _ => panic!("Unknown backend {}", BACKEND),
+    }
+
     let mut stone_count = 0;
     let mut cache = HashMap::new();
     for v in stones {
-        stone_count += split_stone(v, 0, BLINKS, &mut cache);
+        stone_count += split_stone(v, 0, BLINKS, &rules, &mut cache);
         println!("partial_count={}", stone_count);
     }
     println!("stones={}, cache_size={}", stone_count, cache.len());
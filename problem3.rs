@@ -1,41 +1,167 @@
 // After cargo build --release, build with rustc -L target/release/deps problem3.rs
+// Takes an optional path to scan (defaults to input3.txt), or `-` for stdin.
 
-extern crate regex;
+use std::fs::File;
+use std::io::{self, Read};
 
-use regex::Regex;
-use std::fs;
+const CHUNK_SIZE: usize = 64 * 1024;
 
-fn main() {
-    let contents =
-        fs::read_to_string("input3.txt").expect("Should have been able to read the file");
-    // let contents = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
-    // let contents = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanState {
+    Idle,
+    // Matched `len` bytes of `token`, one of `do()`, `don't()` or `mul(`.
+    Literal { token: &'static [u8], len: usize },
+    // Inside `mul(`, reading digits before the ','.
+    // Operands that don't fit an i64 reject the token.
+    Lhs { value: i64, digits: usize },
+    // After the ',', reading digits before the ')'.
+    Rhs { lhs: i64, value: i64, digits: usize },
+}
+
+// Byte-at-a-time matcher for `do()`, `don't()` and `mul(\d+,\d+)`. All state
+// lives in the struct so input can be fed in arbitrary chunks and a token
+// split across two reads is still recognized.
+struct MulScanner {
+    state: ScanState,
+    enabled: bool,
+    // Products of two i64 operands always fit, and adding them up would take
+    // far more input than anyone has to overflow.
+    total: i128,
+}
 
-    let mul_re = Regex::new(r"(do\(\))|(don't\(\))|(mul\(\d+,\d+\))").unwrap();
-    let num_re = Regex::new(r"(\d+)").unwrap();
-
-    let mut enabled: bool = true;
-    let mut total: i64 = 0;
-    for expression in mul_re.captures_iter(&contents) {
-        if expression[0] == *"do()" {
-            enabled = true;
-            continue;
-        } else if expression[0] == *"don't()" {
-            enabled = false;
-            continue;
+impl MulScanner {
+    fn new() -> Self {
+        MulScanner {
+            state: ScanState::Idle,
+            enabled: true,
+            total: 0,
         }
+    }
 
-        if !enabled {
-            continue;
+    fn feed(&mut self, chunk: &[u8]) {
+        for &c in chunk {
+            self.step(c);
         }
+    }
 
-        let mut result: i64 = 1;
-        for value in num_re.captures_iter(&expression[0]) {
-            let num: i64 = (*value.extract::<1>().0).parse().expect("bahg");
-            result *= num;
+    fn step(&mut self, c: u8) {
+        if !self.advance(c) {
+            // No token can start inside a failed partial match other than at
+            // `c` itself, since 'd' and 'm' only appear at token starts.
+            self.state = ScanState::Idle;
+            self.advance(c);
         }
+    }
+
+    // Returns false if `c` can't continue the current partial token.
+    fn advance(&mut self, c: u8) -> bool {
+        match self.state {
+            ScanState::Idle => {
+                self.state = match c {
+                    b'd' => ScanState::Literal {
+                        token: b"do()",
+                        len: 1,
+                    },
+                    b'm' => ScanState::Literal {
+                        token: b"mul(",
+                        len: 1,
+                    },
+                    _ => ScanState::Idle,
+                };
+            }
+            ScanState::Literal { token, len } => {
+                // `do()` and `don't()` share a prefix, so switch tokens on 'n'.
+                let token = if token == b"do()" && len == 2 && c == b'n' {
+                    b"don't()"
+                } else {
+                    token
+                };
+                if token[len] != c {
+                    return false;
+                }
+                if len + 1 < token.len() {
+                    self.state = ScanState::Literal {
+                        token: token,
+                        len: len + 1,
+                    };
+                } else if token == b"mul(" {
+                    self.state = ScanState::Lhs {
+                        value: 0,
+                        digits: 0,
+                    };
+                } else {
+                    self.enabled = token == b"do()";
+                    self.state = ScanState::Idle;
+                }
+            }
+            ScanState::Lhs { value, digits } => match c {
+                b'0'..=b'9' => {
+                    self.state = ScanState::Lhs {
+                        value: match push_digit(value, c) {
+                            Some(value) => value,
+                            None => return false,
+                        },
+                        digits: digits + 1,
+                    };
+                }
+                b',' if digits > 0 => {
+                    self.state = ScanState::Rhs {
+                        lhs: value,
+                        value: 0,
+                        digits: 0,
+                    };
+                }
+                _ => return false,
+            },
+            ScanState::Rhs { lhs, value, digits } => match c {
+                b'0'..=b'9' => {
+                    self.state = ScanState::Rhs {
+                        lhs: lhs,
+                        value: match push_digit(value, c) {
+                            Some(value) => value,
+                            None => return false,
+                        },
+                        digits: digits + 1,
+                    };
+                }
+                b')' if digits > 0 => {
+                    if self.enabled {
+                        self.total += lhs as i128 * value as i128;
+                    }
+                    self.state = ScanState::Idle;
+                }
+                _ => return false,
+            },
+        }
+        return true;
+    }
+}
+
+// `value` with the digit `c` appended, or None if that overflows.
+fn push_digit(value: i64, c: u8) -> Option<i64> {
+    return value.checked_mul(10)?.checked_add((c - b'0') as i64);
+}
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or("input3.txt".to_string());
+    let mut reader: Box<dyn Read> = if path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&path).expect("Unable to open file"))
+    };
+    // let contents = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
+    // let contents = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
 
-        total += result;
+    let mut scanner = MulScanner::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => panic!("Unable to read input: {}", e),
+        };
+        scanner.feed(&buffer[..read]);
     }
-    println!("Match: {}", total);
+    println!("Match: {}", scanner.total);
 }
@@ -3,6 +3,15 @@ use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+// Print the rule set analysis and a minimized rule file after the sums.
+const ANALYZE_RULES: bool = false;
+// Print a JSON line per invalid update with its violated rules and the moves
+// that fix it.
+const EXPLAIN_FIXES: bool = false;
+// With ANALYZE_RULES, also list the orderings only implied by chains of
+// rules, not given directly.
+const LIST_IMPLIED: bool = false;
+
 fn verify_pages(pages: &Vec<i32>, rules_map: &HashMap<i32, HashSet<i32>>) -> bool {
     for i in (1..pages.len()).rev() {
         let page = pages[i];
@@ -20,23 +29,297 @@ fn verify_pages(pages: &Vec<i32>, rules_map: &HashMap<i32, HashSet<i32>>) -> boo
     return true;
 }
 
-fn fix_pages(pages: &Vec<i32>, rules_map: &HashMap<i32, HashSet<i32>>) -> Vec<i32> {
-    let mut fixed = pages.clone();
-    for i in (1..pages.len()).rev() {
-        let page = pages[i];
-        let bad_pages = rules_map.get(&page);
-        if bad_pages.is_none() {
+// Returns each violated rule as (a, b, position of a, position of b), where
+// `b` comes before `a` in `pages`.
+fn violated_rules(
+    pages: &Vec<i32>,
+    rules_map: &HashMap<i32, HashSet<i32>>,
+) -> Vec<(i32, i32, usize, usize)> {
+    let mut violations = Vec::new();
+    for i in 0..pages.len() {
+        for j in i + 1..pages.len() {
+            if rules_map
+                .get(&pages[j])
+                .map_or(false, |later| later.contains(&pages[i]))
+            {
+                violations.push((pages[j], pages[i], j, i));
+            }
+        }
+    }
+    return violations;
+}
+
+// Fewest single page moves turning `pages` into `ordered`, as (page, from, to)
+// applied one after another. Pages on a longest subsequence already in the
+// right relative order stay put, every other page is moved to sit right after
+// its predecessor in `ordered`.
+fn minimal_moves(pages: &Vec<i32>, ordered: &Vec<i32>) -> Vec<(i32, usize, usize)> {
+    let target: Vec<usize> = pages
+        .iter()
+        .map(|p| ordered.iter().position(|o| o == p).unwrap())
+        .collect();
+
+    // O(n^2) longest increasing subsequence, updates are short.
+    let mut length = vec![1; target.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; target.len()];
+    for i in 0..target.len() {
+        for j in 0..i {
+            if target[j] < target[i] && length[j] + 1 > length[i] {
+                length[i] = length[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+    let mut stays = vec![false; ordered.len()];
+    let mut current = (0..target.len()).max_by_key(|i| length[*i]);
+    while let Some(i) = current {
+        stays[target[i]] = true;
+        current = previous[i];
+    }
+
+    let mut working = pages.clone();
+    let mut moves = Vec::new();
+    for (index, page) in ordered.iter().enumerate() {
+        if stays[index] {
             continue;
         }
+        let from = working.iter().position(|p| p == page).unwrap();
+        working.remove(from);
+        let to = if index == 0 {
+            0
+        } else {
+            working
+                .iter()
+                .position(|p| *p == ordered[index - 1])
+                .unwrap()
+                + 1
+        };
+        working.insert(to, *page);
+        moves.push((*page, from, to));
+    }
+    return moves;
+}
 
-        for j in (0..i).rev() {
-            if bad_pages.unwrap().contains(&pages[j]) {
-                fixed.swap(i, j);
-                break;
+fn explain_fix(
+    index: usize,
+    pages: &Vec<i32>,
+    ordered: &Vec<i32>,
+    rules_map: &HashMap<i32, HashSet<i32>>,
+) -> String {
+    let violations: Vec<String> = violated_rules(pages, rules_map)
+        .iter()
+        .map(|&(a, b, pos_a, pos_b)| {
+            format!(
+                "{{\"rule\": \"{}|{}\", \"positions\": [{}, {}]}}",
+                a, b, pos_a, pos_b
+            )
+        })
+        .collect();
+    let moves: Vec<String> = minimal_moves(pages, ordered)
+        .iter()
+        .map(|&(page, from, to)| {
+            format!("{{\"page\": {}, \"from\": {}, \"to\": {}}}", page, from, to)
+        })
+        .collect();
+    return format!(
+        "{{\"update\": {}, \"pages\": {:?}, \"violations\": [{}], \"moves\": [{}], \"fixed\": {:?}}}",
+        index,
+        pages,
+        violations.join(", "),
+        moves.join(", "),
+        ordered
+    );
+}
+
+// Orders `pages` with a topological sort of the rules between them, keeping
+// the original order where the rules don't decide. Returns the pages forming
+// a cycle if the rules can't be satisfied.
+fn order_pages(
+    pages: &Vec<i32>,
+    rules_map: &HashMap<i32, HashSet<i32>>,
+) -> Result<Vec<i32>, Vec<i32>> {
+    let after = |page: &i32| -> Vec<i32> {
+        match rules_map.get(page) {
+            Some(later) => pages
+                .iter()
+                .filter(|p| later.contains(p))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    };
+
+    let mut in_degree: HashMap<i32, usize> = pages.iter().map(|p| (*p, 0)).collect();
+    for page in pages {
+        for later in after(page) {
+            *in_degree.get_mut(&later).unwrap() += 1;
+        }
+    }
+
+    let mut ordered = Vec::new();
+    let mut remaining = pages.clone();
+    while !remaining.is_empty() {
+        let next = remaining.iter().position(|p| in_degree[p] == 0);
+        if next.is_none() {
+            return Err(find_cycle(&remaining, &after));
+        }
+        let page = remaining.remove(next.unwrap());
+        for later in after(&page) {
+            *in_degree.get_mut(&later).unwrap() -= 1;
+        }
+        ordered.push(page);
+    }
+    return Ok(ordered);
+}
+
+// Every page in `remaining` has an unsatisfied predecessor, so walking
+// backwards from any of them must eventually revisit a page.
+fn find_cycle(remaining: &Vec<i32>, after: &dyn Fn(&i32) -> Vec<i32>) -> Vec<i32> {
+    let mut path = vec![remaining[0]];
+    loop {
+        let current = *path.last().unwrap();
+        let previous = *remaining
+            .iter()
+            .find(|p| after(p).contains(&current))
+            .expect("remaining page without predecessor");
+        if let Some(start) = path.iter().position(|p| *p == previous) {
+            let mut cycle: Vec<i32> = path[start..].to_vec();
+            cycle.reverse();
+            return cycle;
+        }
+        path.push(previous);
+    }
+}
+
+// Pages reachable from `page` by following rules between `pages` only, since
+// a rule only applies when both of its pages are in the update.
+fn reachable(
+    page: i32,
+    pages: &HashSet<i32>,
+    rules_map: &HashMap<i32, HashSet<i32>>,
+) -> HashSet<i32> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![page];
+    while let Some(current) = stack.pop() {
+        if let Some(later) = rules_map.get(&current) {
+            for next in later {
+                if pages.contains(next) && seen.insert(*next) {
+                    stack.push(*next);
+                }
+            }
+        }
+    }
+    return seen;
+}
+
+// Prints the transitive closure of the rules, whether each update's pages are
+// totally ordered, and which rules can be dropped without changing how any
+// update is ordered. Finishes with the remaining rules in `a|b` form.
+fn analyze_rules(
+    rules: &Vec<(i32, i32)>,
+    rules_map: &HashMap<i32, HashSet<i32>>,
+    updates: &Vec<Vec<i32>>,
+) {
+    let all_pages: HashSet<i32> = rules.iter().flat_map(|r| vec![r.0, r.1]).collect();
+    let mut closure_size = 0;
+    let mut cyclic = false;
+    let mut implied = Vec::new();
+    for page in &all_pages {
+        let reach = reachable(*page, &all_pages, rules_map);
+        cyclic |= reach.contains(page);
+        closure_size += reach.len();
+        for later in &reach {
+            if !rules_map[page].contains(later) {
+                implied.push((*page, *later));
+            }
+        }
+    }
+    println!(
+        "Rules: {} over {} pages, implied orderings: {}, cyclic: {}",
+        rules.len(),
+        all_pages.len(),
+        closure_size,
+        cyclic
+    );
+    if LIST_IMPLIED {
+        implied.sort();
+        let implied: Vec<String> = implied
+            .iter()
+            .map(|(a, b)| format!("{}|{}", a, b))
+            .collect();
+        println!("Implied rules ({}): {}", implied.len(), implied.join(", "));
+    }
+
+    let mut total_orders = 0;
+    for (index, pages) in updates.iter().enumerate() {
+        let page_set: HashSet<i32> = pages.iter().cloned().collect();
+        let reach: HashMap<i32, HashSet<i32>> = pages
+            .iter()
+            .map(|p| (*p, reachable(*p, &page_set, rules_map)))
+            .collect();
+        let has_cycle = pages.iter().any(|p| reach[p].contains(p));
+        let mut unordered = Vec::new();
+        for i in 0..pages.len() {
+            for j in i + 1..pages.len() {
+                if !reach[&pages[i]].contains(&pages[j]) && !reach[&pages[j]].contains(&pages[i]) {
+                    unordered.push(format!("{}/{}", pages[i], pages[j]));
+                }
             }
         }
+        if !has_cycle && unordered.is_empty() {
+            total_orders += 1;
+            continue;
+        }
+        println!(
+            "Update {} {:?}: cycle: {}, unordered pairs: [{}]",
+            index,
+            pages,
+            has_cycle,
+            unordered.join(", ")
+        );
+    }
+    println!(
+        "Totally ordered updates: {}/{}",
+        total_orders,
+        updates.len()
+    );
+
+    // Greedily drop rules that are implied by the remaining ones in every
+    // update containing both pages. Checking against the rules kept so far
+    // means all of the dropped rules can be removed together.
+    let mut kept_map = rules_map.clone();
+    let mut kept = Vec::new();
+    let mut redundant = Vec::new();
+    let mut unused = Vec::new();
+    for &(a, b) in rules {
+        let page_sets: Vec<HashSet<i32>> = updates
+            .iter()
+            .filter(|u| u.contains(&a) && u.contains(&b))
+            .map(|u| u.iter().cloned().collect())
+            .collect();
+        kept_map.get_mut(&a).unwrap().remove(&b);
+        if page_sets.is_empty() {
+            unused.push(format!("{}|{}", a, b));
+        } else if page_sets
+            .iter()
+            .all(|pages| reachable(a, pages, &kept_map).contains(&b))
+        {
+            redundant.push(format!("{}|{}", a, b));
+        } else {
+            kept_map.get_mut(&a).unwrap().insert(b);
+            kept.push(format!("{}|{}", a, b));
+        }
+    }
+    println!(
+        "Redundant rules ({}): {}",
+        redundant.len(),
+        redundant.join(", ")
+    );
+    println!("Unused rules ({}): {}", unused.len(), unused.join(", "));
+    println!("Minimized rules ({}):", kept.len());
+    for rule in kept {
+        println!("{}", rule);
     }
-    return fixed;
 }
 
 fn main() {
@@ -45,6 +328,8 @@ fn main() {
     let reader = BufReader::new(file);
 
     let mut rules_map = HashMap::new();
+    let mut rules = Vec::new();
+    let mut updates = Vec::new();
     let mut middle_sum: i32 = 0;
     let mut middle_sum_fixed: i32 = 0;
 
@@ -66,6 +351,7 @@ fn main() {
                 .entry(int_rules[0])
                 .or_insert(HashSet::new())
                 .insert(int_rules[1]);
+            rules.push((int_rules[0], int_rules[1]));
             continue;
         }
 
@@ -75,19 +361,34 @@ fn main() {
                 .into_iter()
                 .map(|s| s.parse::<i32>().unwrap())
                 .collect();
+            updates.push(int_pages.clone());
 
             if verify_pages(&int_pages, &rules_map) {
                 middle_sum += int_pages[int_pages.len() / 2];
                 continue;
             } else {
-                let mut new_pages = int_pages;
-                loop {
-                    new_pages = fix_pages(&new_pages, &rules_map);
-                    if verify_pages(&new_pages, &rules_map) {
-                        break;
+                match order_pages(&int_pages, &rules_map) {
+                    Ok(new_pages) => {
+                        if EXPLAIN_FIXES {
+                            println!(
+                                "{}",
+                                explain_fix(updates.len() - 1, &int_pages, &new_pages, &rules_map)
+                            );
+                        }
+                        middle_sum_fixed += new_pages[new_pages.len() / 2];
+                    }
+                    Err(cycle) => {
+                        let mut rules: Vec<String> = Vec::new();
+                        for i in 0..cycle.len() {
+                            rules.push(format!("{}|{}", cycle[i], cycle[(i + 1) % cycle.len()]));
+                        }
+                        eprintln!(
+                            "Can't order {:?}, rules form a cycle: {}",
+                            int_pages,
+                            rules.join(", ")
+                        );
                     }
                 }
-                middle_sum_fixed += new_pages[new_pages.len() / 2];
             }
         }
     }
@@ -96,4 +397,8 @@ fn main() {
         "Middle Sum: {}, w/ Fixed Middle Sum: {}",
         middle_sum, middle_sum_fixed
     );
+
+    if ANALYZE_RULES {
+        analyze_rules(&rules, &rules_map, &updates);
+    }
 }
@@ -1,194 +1,445 @@
-extern crate regex;
-
-use regex::Regex;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::thread;
+
+// Up, right, down, left. Turning right moves to the next entry.
+const DIRS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+// Jump target for walks that leave the map.
+const EXIT: usize = usize::MAX;
+// Print the loop and a rendered map for every loop causing obstruction.
+const SHOW_LOOPS: bool = false;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tile {
+    // '.', or where a guard starts.
+    Open,
+    // '#', guards turn right in front of it.
+    Obstacle,
+    // '@', guards turn left in front of it.
+    TurnLeft,
+    // 'N', 'E', 'S' or 'W'. Can only be entered moving in that direction and
+    // is an obstacle otherwise.
+    OneWay(usize),
+}
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 struct WalkState {
-    start_x: usize,
-    start_y: usize,
-    dir_x: i8,
-    dir_y: i8,
+    pos: usize,
+    dir: usize,
 }
 
-// Reads lines of a file and then pads the data with `pad_len` spaces
-// above and below.
-fn create_padded_input(file_name: &str, pad_len: usize) -> Vec<String> {
-    let file = File::open(file_name).expect("Unable to open file");
-    let reader = BufReader::new(file);
-    let mut input = Vec::new();
+struct Lab {
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    // `jumps[dir][pos]` is the cell a guard at `pos` facing `dir` stops on in
+    // front of the next obstacle, or EXIT if it walks off the map instead.
+    // Only valid for maps without `TurnLeft` or `OneWay` tiles.
+    jumps: [Vec<usize>; 4],
+}
 
-    for line in reader.lines() {
-        let line = line.expect("Unable to read line");
-        let padding = " ".repeat(pad_len);
-        let padded_line = padding.clone() + &line + &padding;
-        if input.is_empty() {
-            for _i in 0..pad_len {
-                input.push(" ".repeat(padded_line.len()));
+impl Lab {
+    fn new(width: usize, height: usize, tiles: Vec<Tile>) -> Lab {
+        let mut lab = Lab {
+            width: width,
+            height: height,
+            tiles: tiles,
+            jumps: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+        };
+
+        for dir in 0..4 {
+            let mut jumps = vec![EXIT; width * height];
+            // Visit cells so the one ahead of `pos` is always filled in first.
+            let (dx, dy) = DIRS[dir];
+            for i in 0..height {
+                let y = if dy < 0 { i } else { height - 1 - i };
+                for j in 0..width {
+                    let x = if dx < 0 { j } else { width - 1 - j };
+                    let pos = y * width + x;
+                    jumps[pos] = match lab.step(pos, dir) {
+                        None => EXIT,
+                        Some(next) if lab.tiles[next] == Tile::Obstacle => pos,
+                        Some(next) => jumps[next],
+                    };
+                }
             }
+            lab.jumps[dir] = jumps;
         }
-        input.push(padded_line);
+        return lab;
     }
 
-    for _i in 0..pad_len {
-        input.push(" ".repeat(input[input.len() - 1].len()));
+    // The neighbour of `pos` in `dir`, if it's on the map.
+    fn step(&self, pos: usize, dir: usize) -> Option<usize> {
+        let x = (pos % self.width) as i32 + DIRS[dir].0;
+        let y = (pos / self.width) as i32 + DIRS[dir].1;
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        return Some(y as usize * self.width + x as usize);
     }
 
-    return input;
-}
-
-// Encodes direction as a single value for cycle testing.
-fn encode_dir(dir_x: i8, dir_y: i8) -> i8 {
-    return 10 * dir_x + dir_y;
-}
+    // True if the map only has open cells and plain obstacles.
+    fn is_simple(&self) -> bool {
+        return self
+            .tiles
+            .iter()
+            .all(|t| *t == Tile::Open || *t == Tile::Obstacle);
+    }
 
-fn rotate_90_degrees(x: i8, y: i8) -> (i8, i8) {
-    (y, -x)
-}
+    // A single move or turn from `state`, or None if the guard leaves the map.
+    // `block` is treated as an extra obstacle.
+    fn next_state(&self, state: WalkState, block: Option<usize>) -> Option<WalkState> {
+        let next = self.step(state.pos, state.dir)?;
+        let turn = |dir: usize| WalkState {
+            pos: state.pos,
+            dir: dir,
+        };
+        if Some(next) == block {
+            return Some(turn((state.dir + 1) % 4));
+        }
+        return Some(match self.tiles[next] {
+            Tile::Obstacle => turn((state.dir + 1) % 4),
+            Tile::TurnLeft => turn((state.dir + 3) % 4),
+            Tile::OneWay(dir) if dir != state.dir => turn((state.dir + 1) % 4),
+            Tile::Open | Tile::OneWay(_) => WalkState {
+                pos: next,
+                dir: state.dir,
+            },
+        });
+    }
 
-// Returns the number of moves to walk to exit or None if no exit exists.
-// `walk_fn` is called on moves to '.' out of initial line of sight.
-fn walk_maze(
-    input_matrix: &Vec<String>,
-    mut visited_matrix: Vec<Vec<i8>>,
-    mut state: WalkState,
-    mut walk_fn: impl FnMut(&WalkState, &WalkState, &Vec<String>, &Vec<Vec<i8>>),
-) -> Option<i32> {
-    if visited_matrix.is_empty() {
-        visited_matrix = vec![vec![0; input_matrix[0].len()]; input_matrix.len()];
+    // Number of steps from `from` to `to` along `dir`, if `to` is ahead.
+    fn distance_ahead(&self, from: usize, to: usize, dir: usize) -> Option<i32> {
+        let (fx, fy) = ((from % self.width) as i32, (from / self.width) as i32);
+        let (tx, ty) = ((to % self.width) as i32, (to / self.width) as i32);
+        let (dx, dy) = DIRS[dir];
+        let distance = (tx - fx) * dx + (ty - fy) * dy;
+        if distance <= 0 || (dx == 0 && tx != fx) || (dy == 0 && ty != fy) {
+            return None;
+        }
+        return Some(distance);
     }
 
-    let mut has_los: bool = true;
-    let mut moves: i32 = 0;
-    visited_matrix[state.start_y][state.start_x] = encode_dir(state.dir_x, state.dir_y);
-    loop {
-        let next_x = (state.start_x as i32 + state.dir_x as i32) as usize;
-        let next_y = (state.start_y as i32 + -state.dir_y as i32) as usize;
-        let next_char = input_matrix[next_y].chars().nth(next_x).expect("FAIL");
+    // Where a guard in `state` stops, treating `block` as an extra obstacle.
+    // The table only needs patching when `block` sits between the guard and
+    // the obstacle it would otherwise hit.
+    fn jump(&self, state: WalkState, block: Option<usize>) -> usize {
+        let stop = self.jumps[state.dir][state.pos];
+        if let Some(block) = block {
+            if let Some(to_block) = self.distance_ahead(state.pos, block, state.dir) {
+                let to_stop = if stop == EXIT {
+                    i32::MAX
+                } else {
+                    self.distance_ahead(state.pos, stop, state.dir).unwrap_or(0)
+                };
+                if to_block <= to_stop {
+                    let (dx, dy) = DIRS[state.dir];
+                    return (block as i32 - dx - dy * self.width as i32) as usize;
+                }
+            }
+        }
+        return stop;
+    }
 
-        match next_char {
-            ' ' => {
-                break;
+    // Returns true if a guard starting at `state` never leaves the map once
+    // `block` is an obstacle. `seen` holds the stamp of the last check that
+    // turned at each (cell, dir), so it never needs clearing between checks.
+    fn loops(&self, mut state: WalkState, block: usize, seen: &mut Vec<u32>, stamp: u32) -> bool {
+        loop {
+            let stop = self.jump(state, Some(block));
+            if stop == EXIT {
+                return false;
             }
-            '#' => {
-                has_los = false;
-                (state.dir_x, state.dir_y) = rotate_90_degrees(state.dir_x, state.dir_y);
+            state = WalkState {
+                pos: stop,
+                dir: (state.dir + 1) % 4,
+            };
+            let key = state.pos * 4 + state.dir;
+            if seen[key] == stamp {
+                return true;
             }
-            '.' | '<' | '>' | '^' | 'v' => {
-                if !has_los && next_char == '.' {
-                    let mut next_state = state.clone();
-                    next_state.start_x = next_x;
-                    next_state.start_y = next_y;
-                    walk_fn(&state, &next_state, &input_matrix, &visited_matrix);
-                }
+            seen[key] = stamp;
+        }
+    }
 
-                let current_dir = encode_dir(state.dir_x, state.dir_y);
-                if visited_matrix[next_y][next_x] == 0 {
-                    visited_matrix[next_y][next_x] = current_dir;
-                    moves += 1;
-                } else {
-                    if visited_matrix[next_y][next_x] == current_dir {
-                        return None;
-                    }
+    // Walks cell by cell from `state` until leaving the map. Returns the
+    // number of cells visited and, for every cell entered for the first time
+    // after the start, the state just before entering it. Those are the only
+    // places an extra obstacle changes the walk, and the walk up to that state
+    // is unaffected by it. Returns None if the guard never leaves.
+    fn walk(&self, mut state: WalkState) -> Option<(usize, Vec<(usize, WalkState)>)> {
+        let mut visited = vec![false; self.width * self.height];
+        let mut turned = vec![false; self.width * self.height * 4];
+        let mut candidates = Vec::new();
+        let mut count = 1;
+        visited[state.pos] = true;
+        while let Some(next) = self.next_state(state, None) {
+            if next.pos == state.pos {
+                if turned[next.pos * 4 + next.dir] {
+                    return None;
                 }
-                state.start_x = next_x;
-                state.start_y = next_y;
+                turned[next.pos * 4 + next.dir] = true;
+            } else if !visited[next.pos] {
+                visited[next.pos] = true;
+                count += 1;
+                candidates.push((next.pos, state));
             }
-            _ => todo!(),
+            state = next;
         }
+        return Some((count, candidates));
     }
-    return Some(moves);
 }
 
-fn main() {
-    let input_matrix = create_padded_input("input6.txt", 1);
-    let mut init_state = WalkState::default();
-    let find_re = Regex::new(r"(\^|<|>|v)").unwrap();
-
-    for (index, line) in input_matrix.iter().enumerate() {
-        for m in find_re.find_iter(&line) {
-            init_state.start_x = m.start();
-            init_state.start_y = index;
-            match m.as_str() {
-                "^" => {
-                    init_state.dir_x = 0;
-                    init_state.dir_y = 1;
-                }
-                ">" => {
-                    init_state.dir_x = 1;
-                    init_state.dir_y = 0;
-                }
-                "<" => {
-                    init_state.dir_x = -1;
-                    init_state.dir_y = 0;
-                }
-                "v" => {
-                    init_state.dir_x = 0;
-                    init_state.dir_y = -1;
-                }
-                _ => todo!(),
-            }
+// Every state of a guard from `state`, one per tick, until it leaves the map
+// or repeats a state, with `block` as an extra obstacle. In the latter case
+// also returns the index the guard loops back to.
+fn trajectory(
+    lab: &Lab,
+    mut state: WalkState,
+    block: Option<usize>,
+) -> (Vec<WalkState>, Option<usize>) {
+    let mut first_seen = vec![usize::MAX; lab.width * lab.height * 4];
+    let mut states = Vec::new();
+    loop {
+        let key = state.pos * 4 + state.dir;
+        if first_seen[key] != usize::MAX {
+            return (states, Some(first_seen[key]));
+        }
+        first_seen[key] = states.len();
+        states.push(state);
+        match lab.next_state(state, block) {
+            Some(next) => state = next,
+            None => return (states, None),
         }
     }
+}
 
-    println!("init_state: {:?}", init_state);
+// Position of a guard at tick `t`, or None once it has left the map.
+fn position_at(states: &Vec<WalkState>, loop_start: Option<usize>, t: usize) -> Option<usize> {
+    if t < states.len() {
+        return Some(states[t].pos);
+    }
+    return loop_start.map(|start| states[start + (t - start) % (states.len() - start)].pos);
+}
 
-    let do_nothing_fn = |_: &WalkState, _: &WalkState, _: &Vec<String>, _: &Vec<Vec<i8>>| {};
-    let moves = walk_maze(&input_matrix, Vec::new(), init_state.clone(), do_nothing_fn);
-    println!("moves: {}", moves.expect("FAIL!") + 1);
+fn gcd(a: usize, b: usize) -> usize {
+    return if b == 0 { a } else { gcd(b, a % b) };
+}
 
-    let mut barrels: Vec<Vec<bool>> = vec![vec![false; input_matrix[0].len()]; input_matrix.len()];
-    let mut cycles: i32 = 0;
+// First tick at which two guards share a cell or swap cells, if any. Once a
+// guard leaves the map it can't collide again, and once both loop their
+// positions repeat after the lcm of the loop lengths.
+fn first_collision(
+    a: &(Vec<WalkState>, Option<usize>),
+    b: &(Vec<WalkState>, Option<usize>),
+) -> Option<(usize, usize)> {
+    let horizon = match (a.1, b.1) {
+        (None, None) => std::cmp::min(a.0.len(), b.0.len()),
+        (Some(_), None) => b.0.len(),
+        (None, Some(_)) => a.0.len(),
+        (Some(loop_a), Some(loop_b)) => {
+            let period_a = a.0.len() - loop_a;
+            let period_b = b.0.len() - loop_b;
+            std::cmp::max(a.0.len(), b.0.len()) + period_a / gcd(period_a, period_b) * period_b
+        }
+    };
 
-    let cycle_finder_fn = |cur_state: &WalkState,
-                           next_state: &WalkState,
-                           cur_input_matrix: &Vec<String>,
-                           cur_visited_matrix: &Vec<Vec<i8>>| {
-        // Ensure we don't try to place a barrel in the same spots.
-        if barrels[next_state.start_y][next_state.start_x] {
-            return;
+    for t in 0..horizon {
+        let (pos_a, pos_b) = (position_at(&a.0, a.1, t), position_at(&b.0, b.1, t));
+        if pos_a.is_some() && pos_a == pos_b {
+            return Some((t, pos_a.unwrap()));
         }
-        barrels[next_state.start_y][next_state.start_x] = true;
-
-        let mut modified_input = cur_input_matrix.clone();
-        modified_input[next_state.start_y]
-            .replace_range(next_state.start_x..next_state.start_x + 1, "#");
-
-        // When there are no other intersecting paths we can reuse
-        // `cur_visited_matrix` to avoid rewalking.
-        if cur_visited_matrix[next_state.start_y][next_state.start_x] == 0 {
-            if walk_maze(
-                &modified_input,
-                cur_visited_matrix.clone(),
-                cur_state.clone(),
-                do_nothing_fn,
-            )
-            .is_none()
-            {
-                cycles += 1;
+        let (next_a, next_b) = (position_at(&a.0, a.1, t + 1), position_at(&b.0, b.1, t + 1));
+        if pos_a.is_some()
+            && pos_b.is_some()
+            && pos_a == next_b
+            && pos_b == next_a
+            && pos_a != pos_b
+        {
+            return Some((t + 1, pos_b.unwrap()));
+        }
+    }
+    return None;
+}
+
+// Moves all guards in lockstep, each tick being one move or one turn, and
+// prints how many cells each visits and the first collision of each pair.
+fn report_guards(lab: &Lab, guards: &Vec<WalkState>) {
+    let trajectories: Vec<(Vec<WalkState>, Option<usize>)> =
+        guards.iter().map(|g| trajectory(lab, *g, None)).collect();
+    for (index, (states, loop_start)) in trajectories.iter().enumerate() {
+        let mut cells: Vec<usize> = states.iter().map(|s| s.pos).collect();
+        cells.sort();
+        cells.dedup();
+        println!(
+            "guard {} at ({}, {}): visited {}, {}",
+            index,
+            guards[index].pos % lab.width,
+            guards[index].pos / lab.width,
+            cells.len(),
+            if loop_start.is_some() {
+                "loops"
+            } else {
+                "exits"
+            }
+        );
+    }
+
+    let mut collided = false;
+    for i in 0..trajectories.len() {
+        for j in i + 1..trajectories.len() {
+            if let Some((t, pos)) = first_collision(&trajectories[i], &trajectories[j]) {
+                collided = true;
+                println!(
+                    "guards {} and {} collide at ({}, {}) on tick {}",
+                    i,
+                    j,
+                    pos % lab.width,
+                    pos / lab.width,
+                    t
+                );
             }
-            return;
         }
-        // We need to rewalk from the beginning to see how the placement changes
-        // the graph.
-        if walk_maze(
-            &modified_input,
-            Vec::new(),
-            init_state.clone(),
-            do_nothing_fn,
-        )
-        .is_none()
-        {
-            cycles += 1;
+    }
+    println!("Collisions: {}", collided);
+}
+
+// Checks every candidate obstruction for a loop, splitting them between
+// threads. Each thread gets its own `seen` buffer since the lab is read only.
+// Returns the candidates that cause a loop.
+fn find_loops(lab: &Lab, candidates: &Vec<(usize, WalkState)>) -> Vec<(usize, WalkState)> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = (candidates.len() + threads - 1) / threads;
+    if chunk_size == 0 {
+        return Vec::new();
+    }
+    return thread::scope(|scope| {
+        let workers: Vec<_> = candidates
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut seen = vec![0; lab.width * lab.height * 4];
+                    let mut loops = Vec::new();
+                    for (stamp, &(block, state)) in chunk.iter().enumerate() {
+                        if lab.loops(state, block, &mut seen, stamp as u32 + 1) {
+                            loops.push((block, state));
+                        }
+                    }
+                    loops
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
+}
+
+// The map with `block` drawn as 'O' and the cells of `path` drawn as '|' or
+// '-' depending on how they're crossed, or '+' if both.
+fn render_loop(lab: &Lab, block: usize, path: &[WalkState]) -> String {
+    let mut crossings = vec![0_u8; lab.width * lab.height];
+    for state in path {
+        crossings[state.pos] |= if state.dir % 2 == 0 { 1 } else { 2 };
+    }
+
+    let mut output = String::new();
+    for pos in 0..lab.width * lab.height {
+        output.push(if pos == block {
+            'O'
+        } else {
+            match (lab.tiles[pos], crossings[pos]) {
+                (_, 1) => '|',
+                (_, 2) => '-',
+                (_, 3) => '+',
+                (Tile::Open, _) => '.',
+                (Tile::Obstacle, _) => '#',
+                (Tile::TurnLeft, _) => '@',
+                (Tile::OneWay(dir), _) => "NESW".chars().nth(dir).unwrap(),
+            }
+        });
+        if pos % lab.width == lab.width - 1 {
+            output.push('\n');
         }
-    };
+    }
+    return output;
+}
+
+// Prints the states making up the loop caused by each obstruction, as
+// (x, y, direction), along with the loop length and a rendered map.
+fn show_loops(lab: &Lab, loops: &Vec<(usize, WalkState)>) {
+    for &(block, state) in loops {
+        let (states, loop_start) = trajectory(lab, state, Some(block));
+        let path = &states[loop_start.expect("obstruction doesn't loop")..];
+        let steps: Vec<String> = path
+            .iter()
+            .map(|s| {
+                format!(
+                    "({}, {}, {})",
+                    s.pos % lab.width,
+                    s.pos / lab.width,
+                    "^>v<".chars().nth(s.dir).unwrap()
+                )
+            })
+            .collect();
+        println!(
+            "obstruction ({}, {}): loop length {}: {}",
+            block % lab.width,
+            block / lab.width,
+            path.len(),
+            steps.join(" ")
+        );
+        print!("{}", render_loop(lab, block, path));
+    }
+}
 
-    walk_maze(
-        &input_matrix,
-        Vec::new(),
-        init_state.clone(),
-        cycle_finder_fn,
-    );
-    println!("Cycles: {}", cycles);
+fn main() {
+    let file = File::open("input6.txt").expect("Unable to open file");
+    let reader = BufReader::new(file);
+    let mut tiles = Vec::new();
+    let mut guards = Vec::new();
+    let mut width = 0;
+    let mut height = 0;
+
+    for line in reader.lines() {
+        let line = line.expect("Unable to read line");
+        width = line.chars().count();
+        for (x, c) in line.chars().enumerate() {
+            let pos = height * width + x;
+            tiles.push(match c {
+                '.' => Tile::Open,
+                '#' => Tile::Obstacle,
+                '@' => Tile::TurnLeft,
+                'N' | 'E' | 'S' | 'W' => Tile::OneWay("NESW".find(c).unwrap()),
+                '^' | '>' | 'v' | '<' => {
+                    guards.push(WalkState {
+                        pos: pos,
+                        dir: "^>v<".find(c).unwrap(),
+                    });
+                    Tile::Open
+                }
+                _ => panic!("Unknown tile '{}'", c),
+            });
+        }
+        height += 1;
+    }
+
+    let lab = Lab::new(width, height, tiles);
+    if guards.len() != 1 || !lab.is_simple() {
+        report_guards(&lab, &guards);
+        return;
+    }
+
+    let init_state = guards[0];
+    println!("init_state: {:?}", init_state);
+
+    let (moves, candidates) = lab.walk(init_state).expect("FAIL!");
+    println!("moves: {}", moves);
+
+    let loops = find_loops(&lab, &candidates);
+    if SHOW_LOOPS {
+        show_loops(&lab, &loops);
+    }
+    println!("Cycles: {}", loops.len());
 }
@@ -1,63 +1,518 @@
+// After cargo build --release, build with rustc -L target/release/deps problem7.rs
+
+extern crate num_bigint;
+
+use num_bigint::{BigInt, Sign};
+use std::convert::TryFrom;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-#[derive(Debug, Default, Clone)]
-struct EqPart {
-    total: i64,
-    values: Vec<i64>,
+// Operator sets to try, each on the equations the previous sets left unsolved.
+const OPERATOR_SETS: [&[&str]; 2] = [&["*", "+"], &["*", "+", "||"]];
+// "u128" or "bigint".
+const BACKEND: &str = "u128";
+const PRECEDENCE: PrecedenceMode = PrecedenceMode::LeftToRight;
+// Print a solution for each solved equation, e.g. "3267: 81 + 40 * 27".
+const PRINT_SOLUTIONS: bool = false;
+// Also count every distinct operator assignment. Slower, as the search can't
+// stop at the first solution.
+const COUNT_SOLUTIONS: bool = false;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PrecedenceMode {
+    // Evaluate strictly left to right, as the puzzle does.
+    LeftToRight,
+    // Operators with a higher `precedence()` bind tighter, ties go left to
+    // right.
+    Standard,
 }
 
-fn mul_op(a: i64, b: i64) -> i64 {
-    return a * b;
+// Arithmetic backend. Every operation returns None instead of overflowing or
+// producing a value the backend can't hold.
+trait Value: Clone + PartialEq + Display + Sized + Send + Sync {
+    fn parse(s: &str) -> Option<Self>;
+    fn zero() -> Self;
+    fn is_negative(&self) -> bool;
+    fn plus(&self, b: &Self) -> Option<Self>;
+    fn minus(&self, b: &Self) -> Option<Self>;
+    fn times(&self, b: &Self) -> Option<Self>;
+    // Some only if `b` divides `self` exactly.
+    fn divide(&self, b: &Self) -> Option<Self>;
+    fn power(&self, b: &Self) -> Option<Self>;
+    fn bit_xor(&self, b: &Self) -> Option<Self>;
+}
+
+impl Value for u128 {
+    fn parse(s: &str) -> Option<Self> {
+        return s.parse().ok();
+    }
+    fn zero() -> Self {
+        return 0;
+    }
+    fn is_negative(&self) -> bool {
+        return false;
+    }
+    fn plus(&self, b: &Self) -> Option<Self> {
+        return self.checked_add(*b);
+    }
+    fn minus(&self, b: &Self) -> Option<Self> {
+        return self.checked_sub(*b);
+    }
+    fn times(&self, b: &Self) -> Option<Self> {
+        return self.checked_mul(*b);
+    }
+    fn divide(&self, b: &Self) -> Option<Self> {
+        return if *b != 0 && self % b == 0 {
+            Some(self / b)
+        } else {
+            None
+        };
+    }
+    fn power(&self, b: &Self) -> Option<Self> {
+        return u32::try_from(*b).ok().and_then(|b| self.checked_pow(b));
+    }
+    fn bit_xor(&self, b: &Self) -> Option<Self> {
+        return Some(self ^ b);
+    }
 }
 
-fn add_op(a: i64, b: i64) -> i64 {
-    return a + b;
+impl Value for BigInt {
+    fn parse(s: &str) -> Option<Self> {
+        return s.parse().ok();
+    }
+    fn zero() -> Self {
+        return BigInt::from(0);
+    }
+    fn is_negative(&self) -> bool {
+        return self.sign() == Sign::Minus;
+    }
+    fn plus(&self, b: &Self) -> Option<Self> {
+        return Some(self + b);
+    }
+    fn minus(&self, b: &Self) -> Option<Self> {
+        return Some(self - b);
+    }
+    fn times(&self, b: &Self) -> Option<Self> {
+        return Some(self * b);
+    }
+    fn divide(&self, b: &Self) -> Option<Self> {
+        if *b == BigInt::zero() || self % b != BigInt::zero() {
+            return None;
+        }
+        return Some(self / b);
+    }
+    fn power(&self, b: &Self) -> Option<Self> {
+        return u32::try_from(b).ok().map(|b| self.pow(b));
+    }
+    fn bit_xor(&self, b: &Self) -> Option<Self> {
+        return Some(self ^ b);
+    }
 }
 
-fn cat_op(a: i64, b: i64) -> i64 {
-    return a * 10_i64.pow((b.ilog10() + 1) as u32) + b;
+trait Operator<V: Value>: Send + Sync {
+    fn name(&self) -> &str;
+    // `a op b`, or None if it isn't representable.
+    fn apply(&self, a: &V, b: &V) -> Option<V>;
+    // Given `a op b = total` and `b`, returns `a` if one exists. Only used if
+    // `has_inverse()`.
+    fn inverse(&self, _total: &V, _b: &V) -> Option<V> {
+        return None;
+    }
+    fn has_inverse(&self) -> bool {
+        return false;
+    }
+    // Binding strength in `PrecedenceMode::Standard`.
+    fn precedence(&self) -> u8;
 }
 
-// Finds lines of the form [total = a, b, c, d] which have solutions
-// after trying all combinations of `op_array`. Returns the sum and
-// unsolved portions.
-fn apply_ops(input_vec: Vec<EqPart>, op_array: Vec<fn(i64, i64) -> i64>) -> (i64, Vec<EqPart>) {
-    let mut unsolved: Vec<EqPart> = Vec::new();
-    let mut sum: i64 = 0;
-    for eq in input_vec {
-        let mut solved = false;
-        let op_count = op_array.len().pow((eq.values.len() - 1) as u32);
-        for i in 0..op_count {
-            let mut mask = i;
-            let mut result = eq.values[0];
-            for j in 1..eq.values.len() {
-                result = op_array[mask % op_array.len()](result, eq.values[j]);
-                if result > eq.total {
-                    break;
+struct AddOp;
+struct SubOp;
+struct MulOp;
+struct CatOp;
+struct PowOp;
+struct XorOp;
+
+impl<V: Value> Operator<V> for AddOp {
+    fn name(&self) -> &str {
+        return "+";
+    }
+    fn apply(&self, a: &V, b: &V) -> Option<V> {
+        return a.plus(b);
+    }
+    fn inverse(&self, total: &V, b: &V) -> Option<V> {
+        return total.minus(b);
+    }
+    fn has_inverse(&self) -> bool {
+        return true;
+    }
+    fn precedence(&self) -> u8 {
+        return 1;
+    }
+}
+
+impl<V: Value> Operator<V> for SubOp {
+    fn name(&self) -> &str {
+        return "-";
+    }
+    fn apply(&self, a: &V, b: &V) -> Option<V> {
+        return a.minus(b);
+    }
+    fn inverse(&self, total: &V, b: &V) -> Option<V> {
+        return total.plus(b);
+    }
+    fn has_inverse(&self) -> bool {
+        return true;
+    }
+    fn precedence(&self) -> u8 {
+        return 1;
+    }
+}
+
+impl<V: Value> Operator<V> for MulOp {
+    fn name(&self) -> &str {
+        return "*";
+    }
+    fn apply(&self, a: &V, b: &V) -> Option<V> {
+        return a.times(b);
+    }
+    // `a * 0` doesn't determine `a`, so there's no inverse for `b == 0`.
+    // `Solver::solve` searches forward when any value is zero.
+    fn inverse(&self, total: &V, b: &V) -> Option<V> {
+        return total.divide(b);
+    }
+    fn has_inverse(&self) -> bool {
+        return true;
+    }
+    fn precedence(&self) -> u8 {
+        return 2;
+    }
+}
+
+// Concatenates the decimal digits, only defined for non-negative values.
+impl<V: Value> Operator<V> for CatOp {
+    fn name(&self) -> &str {
+        return "||";
+    }
+    fn apply(&self, a: &V, b: &V) -> Option<V> {
+        if a.is_negative() || b.is_negative() {
+            return None;
+        }
+        return V::parse(&format!("{}{}", a, b));
+    }
+    fn inverse(&self, total: &V, b: &V) -> Option<V> {
+        if total.is_negative() || b.is_negative() {
+            return None;
+        }
+        let (total, b) = (total.to_string(), b.to_string());
+        if !total.ends_with(&b) {
+            return None;
+        }
+        let prefix = &total[..total.len() - b.len()];
+        return if prefix.is_empty() {
+            Some(V::zero())
+        } else {
+            V::parse(prefix)
+        };
+    }
+    fn has_inverse(&self) -> bool {
+        return true;
+    }
+    fn precedence(&self) -> u8 {
+        return 4;
+    }
+}
+
+// Exponentiation. Has no cheap inverse, so sets using it are searched
+// forwards.
+impl<V: Value> Operator<V> for PowOp {
+    fn name(&self) -> &str {
+        return "^";
+    }
+    fn apply(&self, a: &V, b: &V) -> Option<V> {
+        return a.power(b);
+    }
+    fn precedence(&self) -> u8 {
+        return 3;
+    }
+}
+
+impl<V: Value> Operator<V> for XorOp {
+    fn name(&self) -> &str {
+        return "xor";
+    }
+    fn apply(&self, a: &V, b: &V) -> Option<V> {
+        return a.bit_xor(b);
+    }
+    fn inverse(&self, total: &V, b: &V) -> Option<V> {
+        return total.bit_xor(b);
+    }
+    fn has_inverse(&self) -> bool {
+        return true;
+    }
+    fn precedence(&self) -> u8 {
+        return 0;
+    }
+}
+
+struct Registry<V: Value> {
+    operators: Vec<Box<dyn Operator<V>>>,
+}
+
+impl<V: Value> Registry<V> {
+    fn with_builtins() -> Self {
+        let mut registry = Registry {
+            operators: Vec::new(),
+        };
+        registry.register(Box::new(AddOp));
+        registry.register(Box::new(SubOp));
+        registry.register(Box::new(MulOp));
+        registry.register(Box::new(CatOp));
+        registry.register(Box::new(PowOp));
+        registry.register(Box::new(XorOp));
+        return registry;
+    }
+
+    fn register(&mut self, op: Box<dyn Operator<V>>) {
+        self.operators.push(op);
+    }
+
+    fn select(&self, names: &[&str]) -> Vec<&dyn Operator<V>> {
+        return names
+            .iter()
+            .map(|name| {
+                self.operators
+                    .iter()
+                    .find(|op| op.name() == *name)
+                    .unwrap_or_else(|| panic!("Unknown operator {}", name))
+                    .as_ref()
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct EqPart<V> {
+    total: V,
+    values: Vec<V>,
+}
+
+struct Solver<'a, V: Value + 'a> {
+    ops: Vec<&'a dyn Operator<V>>,
+    mode: PrecedenceMode,
+    // Keep searching after the first solution to count all of them.
+    count_all: bool,
+}
+
+struct Solution<'a, V: Value + 'a> {
+    // Operator between each pair of values, left to right.
+    ops: Vec<&'a dyn Operator<V>>,
+    // Number of distinct operator assignments, if `count_all` was set.
+    count: Option<u64>,
+}
+
+impl<'a, V: Value> Solver<'a, V> {
+    fn solve(&self, eq: &EqPart<V>) -> Option<Solution<'a, V>> {
+        let mut chosen = Vec::new();
+        let mut found = None;
+        let mut count = 0;
+        // `a * 0` loses `a`, so zeros can't be peeled off backwards.
+        if self.mode == PrecedenceMode::LeftToRight
+            && self.ops.iter().all(|op| op.has_inverse())
+            && !eq.values.contains(&V::zero())
+        {
+            self.search_backward(&eq.total, &eq.values, &mut chosen, &mut found, &mut count);
+        } else {
+            self.search_forward(
+                &eq.total,
+                &[eq.values[0].clone()],
+                &[],
+                &eq.values[1..],
+                &mut chosen,
+                &mut found,
+                &mut count,
+            );
+        }
+        return found.map(|ops| Solution {
+            ops: ops,
+            count: if self.count_all { Some(count) } else { None },
+        });
+    }
+
+    // Notes `chosen` as a solution, reversing it first for backward searches.
+    // Returns true if the search can stop.
+    fn record(
+        &self,
+        chosen: &Vec<&'a dyn Operator<V>>,
+        reversed: bool,
+        found: &mut Option<Vec<&'a dyn Operator<V>>>,
+        count: &mut u64,
+    ) -> bool {
+        *count += 1;
+        if found.is_none() {
+            let mut ops = chosen.clone();
+            if reversed {
+                ops.reverse();
+            }
+            *found = Some(ops);
+        }
+        return !self.count_all;
+    }
+
+    // Works right to left, peeling the last value off `total` with each
+    // operator's inverse. Branches where no inverse exists are dropped right
+    // away, which keeps long equations tractable.
+    fn search_backward(
+        &self,
+        total: &V,
+        values: &[V],
+        chosen: &mut Vec<&'a dyn Operator<V>>,
+        found: &mut Option<Vec<&'a dyn Operator<V>>>,
+        count: &mut u64,
+    ) -> bool {
+        let (last, rest) = values.split_last().expect("empty equation");
+        if rest.is_empty() {
+            return total == last && self.record(chosen, true, found, count);
+        }
+        for op in &self.ops {
+            if let Some(prev) = op.inverse(total, last) {
+                chosen.push(*op);
+                let stop = self.search_backward(&prev, rest, chosen, found, count);
+                chosen.pop();
+                if stop {
+                    return true;
                 }
-                mask /= op_array.len();
             }
+        }
+        return false;
+    }
+
+    // Tries every operator between `operands` and the next value. In
+    // `Standard` mode pending operators are kept on a stack and only reduced
+    // once a weaker operator follows them.
+    fn search_forward(
+        &self,
+        total: &V,
+        operands: &[V],
+        pending: &[&'a dyn Operator<V>],
+        rest: &[V],
+        chosen: &mut Vec<&'a dyn Operator<V>>,
+        found: &mut Option<Vec<&'a dyn Operator<V>>>,
+        count: &mut u64,
+    ) -> bool {
+        if rest.is_empty() {
+            let mut operands = operands.to_vec();
+            let mut pending = pending.to_vec();
+            return self.reduce(&mut operands, &mut pending, 0)
+                && operands[0] == *total
+                && self.record(chosen, false, found, count);
+        }
+        for op in &self.ops {
+            let mut operands = operands.to_vec();
+            let mut pending = pending.to_vec();
+            let min_precedence = match self.mode {
+                PrecedenceMode::LeftToRight => 0,
+                PrecedenceMode::Standard => op.precedence(),
+            };
+            if !self.reduce(&mut operands, &mut pending, min_precedence) {
+                continue;
+            }
+            operands.push(rest[0].clone());
+            pending.push(*op);
+            chosen.push(*op);
+            let stop =
+                self.search_forward(total, &operands, &pending, &rest[1..], chosen, found, count);
+            chosen.pop();
+            if stop {
+                return true;
+            }
+        }
+        return false;
+    }
 
-            if result == eq.total {
-                sum += eq.total;
-                solved = true;
+    // Applies pending operators binding at least as tightly as
+    // `min_precedence`. Returns false if a result isn't representable.
+    fn reduce(
+        &self,
+        operands: &mut Vec<V>,
+        pending: &mut Vec<&'a dyn Operator<V>>,
+        min_precedence: u8,
+    ) -> bool {
+        while let Some(op) = pending.last().cloned() {
+            if self.mode == PrecedenceMode::Standard && op.precedence() < min_precedence {
                 break;
             }
+            pending.pop();
+            let b = operands.pop().unwrap();
+            let a = operands.pop().unwrap();
+            match op.apply(&a, &b) {
+                Some(v) => operands.push(v),
+                None => return false,
+            }
         }
+        return true;
+    }
+}
+
+// Prints e.g. "3267: 81 + 40 * 27", followed by the solution count if known
+// and the operators it counts with.
+fn print_solution<V: Value>(eq: &EqPart<V>, solution: &Solution<V>, solver: &Solver<V>) {
+    let mut expression = eq.values[0].to_string();
+    for (op, value) in solution.ops.iter().zip(eq.values[1..].iter()) {
+        expression += &format!(" {} {}", op.name(), value);
+    }
+    match solution.count {
+        Some(count) => {
+            let names: Vec<&str> = solver.ops.iter().map(|op| op.name()).collect();
+            println!(
+                "{}: {} (solutions with {}: {})",
+                eq.total,
+                expression,
+                names.join(", "),
+                count
+            )
+        }
+        None => println!("{}: {}", eq.total, expression),
+    }
+}
 
-        if !solved {
-            unsolved.push(eq);
+fn new_solver<V: Value>(ops: Vec<&dyn Operator<V>>) -> Solver<'_, V> {
+    return Solver {
+        ops: ops,
+        mode: PRECEDENCE,
+        count_all: COUNT_SOLUTIONS,
+    };
+}
+
+// Finds lines of the form [total = a, b, c, d] which have solutions with
+// some combination of `ops`. Returns the sum and unsolved portions.
+fn apply_ops<V: Value>(
+    input_vec: Vec<EqPart<V>>,
+    ops: Vec<&dyn Operator<V>>,
+) -> (V, Vec<EqPart<V>>) {
+    let solver = new_solver(ops);
+    let mut unsolved: Vec<EqPart<V>> = Vec::new();
+    let mut sum = V::zero();
+    for eq in input_vec {
+        match solver.solve(&eq) {
+            Some(solution) => {
+                if PRINT_SOLUTIONS {
+                    print_solution(&eq, &solution, &solver);
+                }
+                sum = sum.plus(&eq.total).expect("sum overflow");
+            }
+            None => unsolved.push(eq),
         }
     }
     return (sum, unsolved);
 }
 
-fn main() {
-    let file = File::open("input7.txt").expect("Unable to open file");
+fn run<V: Value>(file_name: &str) {
+    let file = File::open(file_name).expect("Unable to open file");
     let reader = BufReader::new(file);
+    let registry: Registry<V> = Registry::with_builtins();
     let mut input = Vec::new();
-    let mut sum: i64 = 0;
+    let mut sum = V::zero();
     let mut total_count: usize = 0;
 
     for line in reader.lines() {
@@ -65,31 +520,42 @@ fn main() {
         total_count += 1;
 
         let eq_parts: Vec<&str> = line.split(':').collect();
-        let val_parts: Vec<&str> = eq_parts[1].split_whitespace().collect();
-
-        let total: i64 = eq_parts[0].parse().expect("fail");
-        let mut values: Vec<i64> = Vec::new();
-        let mut mul_total: i64 = 1;
-        let mut sum_total: i64 = 0;
-        for v in val_parts {
-            let iv: i64 = v.parse().expect("fail");
-            mul_total *= iv;
-            sum_total += iv;
-            values.push(iv);
-        }
+        let total = V::parse(eq_parts[0]).expect("fail");
+        let values: Vec<V> = eq_parts[1]
+            .split_whitespace()
+            .map(|v| V::parse(v).expect("fail"))
+            .collect();
 
         // ignore trivial solves.
-        if mul_total == total || sum_total == total {
-            sum += total;
-            continue;
+        if PRECEDENCE == PrecedenceMode::LeftToRight {
+            let mul_total = values[1..]
+                .iter()
+                .try_fold(values[0].clone(), |a, b| a.times(b));
+            let sum_total = values[1..]
+                .iter()
+                .try_fold(values[0].clone(), |a, b| a.plus(b));
+            if mul_total.as_ref() == Some(&total) || sum_total.as_ref() == Some(&total) {
+                sum = sum.plus(&total).expect("sum overflow");
+                if PRINT_SOLUTIONS {
+                    let eq = EqPart {
+                        total: total,
+                        values: values,
+                    };
+                    // Solve again with the widest set, so any count covers
+                    // every operator the later passes could use.
+                    let solver =
+                        new_solver(registry.select(OPERATOR_SETS[OPERATOR_SETS.len() - 1]));
+                    let solution = solver.solve(&eq).expect("trivial solve failed");
+                    print_solution(&eq, &solution, &solver);
+                }
+                continue;
+            }
         }
 
-        let part = EqPart {
+        input.push(EqPart {
             total: total,
             values: values,
-        };
-
-        input.push(part);
+        });
     }
 
     println!(
@@ -99,25 +565,24 @@ fn main() {
         sum
     );
 
-    let op_array = vec![mul_op, add_op];
-    let (sum_partial, unsolved) = apply_ops(input, op_array);
-    sum += sum_partial;
+    for names in OPERATOR_SETS.iter() {
+        let (sum_partial, unsolved) = apply_ops(input, registry.select(names));
+        sum = sum.plus(&sum_partial).expect("sum overflow");
+        input = unsolved;
 
-    println!(
-        "Solved {}/{}, Sum: {}",
-        total_count - unsolved.len(),
-        total_count,
-        sum
-    );
-
-    let op_array2 = vec![mul_op, add_op, cat_op];
-    let (sum_partial2, unsolved2) = apply_ops(unsolved, op_array2);
-    sum += sum_partial2;
+        println!(
+            "Solved {}/{}, Sum: {}",
+            total_count - input.len(),
+            total_count,
+            sum
+        );
+    }
+}
 
-    println!(
-        "Solved {}/{}, Sum: {}",
-        total_count - unsolved2.len(),
-        total_count,
-        sum
-    );
+fn main() {
+    match BACKEND {
+        "u128" => run::<u128>("input7.txt"),
+        "bigint" => run::<BigInt>("input7.txt"),
+        _ => panic!("Unknown backend {}", BACKEND),
+    }
 }
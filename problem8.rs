@@ -8,96 +8,250 @@ use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+// Print antinode counts per frequency for each rule.
+const SHOW_FREQUENCIES: bool = false;
+// Name of the rule whose antinodes to draw on the map, if any.
+const RENDER_RULE: &str = "";
+
+// Any number of dimensions, e.g. [x, y] for the puzzle map or [x, y, z] for
+// antennas with an altitude.
 #[derive(Debug, Default, Clone, Eq, Hash, PartialEq)]
 struct Point {
-    x: i64,
-    y: i64,
+    coords: Vec<i64>,
+}
+
+#[derive(Debug, Clone)]
+enum AntinodeRule {
+    // For each k, the points k times the antenna distance beyond either
+    // antenna. [1] is part 1.
+    Harmonics(Vec<i64>),
+    // Every k >= 0 until leaving the map, which is part 2. With `gcd_step`
+    // the line is walked in steps of the antenna distance divided by the gcd
+    // of its coordinates, so every lattice point on the line is included.
+    AllHarmonics { gcd_step: bool },
+    // Lattice points dividing the segment between the antennas in the ratio
+    // m:n, internally or externally, from either end. Ratio(2, 1) is part 1
+    // plus any points a third of the way between the antennas.
+    Ratio(i64, i64),
+}
+
+impl AntinodeRule {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            &AntinodeRule::Ratio(m, n) if m + n == 0 => {
+                Err(format!("Ratio({}, {}) divides by m + n = 0", m, n))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    return if b == 0 { a } else { gcd(b, a % b) };
 }
 
 fn is_in_bounds(point: &Point, extents: &Point) -> bool {
-    return point.x >= 0 && point.y >= 0 && point.x < extents.x && point.y < extents.y;
+    return point
+        .coords
+        .iter()
+        .zip(extents.coords.iter())
+        .all(|(c, e)| *c >= 0 && c < e);
 }
 
-fn main() {
-    let file = File::open("input8.txt").expect("Unable to open file");
+// `a * scale_a + b * scale_b`, divided by `divisor` if every coordinate
+// divides evenly.
+fn combine(a: &Point, scale_a: i64, b: &Point, scale_b: i64, divisor: i64) -> Option<Point> {
+    let mut coords = Vec::new();
+    for (ca, cb) in a.coords.iter().zip(b.coords.iter()) {
+        let c = ca * scale_a + cb * scale_b;
+        if c % divisor != 0 {
+            return None;
+        }
+        coords.push(c / divisor);
+    }
+    return Some(Point { coords: coords });
+}
+
+// Antinodes of the antenna pair `p1`, `p2` under `rule` that lie in bounds.
+fn antinodes(rule: &AntinodeRule, p1: &Point, p2: &Point, extents: &Point) -> Vec<Point> {
+    let mut result = Vec::new();
+    if p1 == p2 {
+        // Stacked antennas don't define a line.
+        return result;
+    }
+    match rule {
+        &AntinodeRule::Harmonics(ref ks) => {
+            for k in ks {
+                // p1 + k * (p1 - p2) and p2 - k * (p1 - p2).
+                result.push(combine(p1, 1 + k, p2, -k, 1).unwrap());
+                result.push(combine(p2, 1 + k, p1, -k, 1).unwrap());
+            }
+        }
+        &AntinodeRule::AllHarmonics { gcd_step } => {
+            let distance = combine(p1, 1, p2, -1, 1).unwrap();
+            let g = if gcd_step {
+                distance.coords.iter().fold(0, |g, c| gcd(g, c.abs()))
+            } else {
+                1
+            };
+            let step = combine(&distance, 1, &distance, 0, g).unwrap();
+            // Walk away from p1 in both directions, passing p2 on the way.
+            for sign in [1, -1].iter() {
+                let mut k = 0;
+                loop {
+                    let next = combine(p1, 1, &step, sign * k, 1).unwrap();
+                    if !is_in_bounds(&next, extents) {
+                        break;
+                    }
+                    result.push(next);
+                    k += 1;
+                }
+            }
+        }
+        &AntinodeRule::Ratio(m, n) => {
+            for &(from, to) in [(p1, p2), (p2, p1)].iter() {
+                result.extend(combine(from, n, to, m, m + n));
+                if m != n {
+                    result.extend(combine(from, n, to, -m, n - m));
+                }
+            }
+        }
+    }
+    return result
+        .into_iter()
+        .filter(|p| is_in_bounds(p, extents))
+        .collect();
+}
+
+// Reads either the puzzle's character map, or a list of antennas with any
+// number of dimensions in the form below. Every antenna in the list has to
+// come after `extents` and have as many coordinates.
+//
+//   extents 50,50,10
+//   a 3,4,1
+//   ...
+fn read_antennas(file_name: &str) -> Result<(HashMap<String, Vec<Point>>, Point), String> {
+    let file = File::open(file_name).expect("Unable to open file");
     let reader = BufReader::new(file);
     let mut input = HashMap::new();
-    let mut extents = Point { x: 0, y: 0 };
+    let mut extents = Point { coords: vec![0, 0] };
+    let mut has_extents = false;
+    let parse_coords = |s: &str| Point {
+        coords: s.split(',').map(|c| c.parse().expect("fail")).collect(),
+    };
 
     for line in reader.lines() {
         let line = line.expect("Unable to read line");
-        extents.x = line.len() as i64;
+        if line.starts_with("extents ") {
+            extents = parse_coords(&line["extents ".len()..]);
+            has_extents = true;
+            continue;
+        }
+        if let Some((freq, coords)) = line.split_once(' ') {
+            if !has_extents {
+                return Err(format!("Antenna '{}' comes before the extents line", line));
+            }
+            let point = parse_coords(coords);
+            if point.coords.len() != extents.coords.len() {
+                return Err(format!(
+                    "Antenna '{}' has {} coordinates, extents have {}",
+                    line,
+                    point.coords.len(),
+                    extents.coords.len()
+                ));
+            }
+            input
+                .entry(freq.to_string())
+                .or_insert(Vec::new())
+                .push(point);
+            continue;
+        }
+
+        extents.coords[0] = line.len() as i64;
         for (x, c) in line.chars().enumerate() {
             match c {
                 '.' => {}
-                _ => input.entry(c).or_insert(Vec::new()).push(Point {
-                    x: x as i64,
-                    y: extents.y,
-                }),
+                _ => input
+                    .entry(c.to_string())
+                    .or_insert(Vec::new())
+                    .push(Point {
+                        coords: vec![x as i64, extents.coords[1]],
+                    }),
             }
         }
-        extents.y += 1;
+        extents.coords[1] += 1;
     }
+    return Ok((input, extents));
+}
 
-    println!("[0, 0, {:?}]", extents);
-    let mut unique_antinodes: HashSet<Point> = HashSet::new();
-    let mut extended_antinodes: HashSet<Point> = HashSet::new();
-
-    for (_, points) in input {
-        for combination in points.iter().combinations(2) {
-            let p1 = &combination[0];
-            let p2 = &combination[1];
-            extended_antinodes.insert((*p1).clone());
-            extended_antinodes.insert((*p2).clone());
-
-            let distance = Point {
-                x: p1.x - p2.x,
-                y: p1.y - p2.y,
-            };
+// The 2D map with antennas drawn by frequency and remaining antinodes as '#',
+// like the puzzle's examples.
+fn render(
+    input: &HashMap<String, Vec<Point>>,
+    antinodes: &HashSet<Point>,
+    extents: &Point,
+) -> String {
+    if extents.coords.len() != 2 {
+        return String::from("Can only render 2D maps\n");
+    }
+    let (width, height) = (extents.coords[0] as usize, extents.coords[1] as usize);
+    let mut map = vec![vec![".".to_string(); width]; height];
+    for point in antinodes {
+        map[point.coords[1] as usize][point.coords[0] as usize] = "#".to_string();
+    }
+    for (freq, points) in input {
+        for point in points {
+            map[point.coords[1] as usize][point.coords[0] as usize] = freq.clone();
+        }
+    }
+    return map.iter().map(|row| row.concat() + "\n").collect();
+}
 
-            let pos_antinode = Point {
-                x: p1.x + distance.x,
-                y: p1.y + distance.y,
-            };
-            if is_in_bounds(&pos_antinode, &extents) {
-                let mut next_antinode = pos_antinode.clone();
-                unique_antinodes.insert(pos_antinode);
-                extended_antinodes.insert(next_antinode.clone());
-                loop {
-                    next_antinode.x += distance.x;
-                    next_antinode.y += distance.y;
-                    if is_in_bounds(&next_antinode, &extents) {
-                        extended_antinodes.insert(next_antinode.clone());
-                    } else {
-                        break;
-                    }
-                }
-            }
+fn main() {
+    let file_name = "input8.txt";
+    // let file_name = "input8-test.txt";
+    let (input, extents) = match read_antennas(file_name) {
+        Ok(antennas) => antennas,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
 
-            let neg_antinode = Point {
-                x: p2.x - distance.x,
-                y: p2.y - distance.y,
-            };
-            if is_in_bounds(&neg_antinode, &extents) {
-                let mut next_antinode = neg_antinode.clone();
-                unique_antinodes.insert(neg_antinode);
-                extended_antinodes.insert(next_antinode.clone());
-                loop {
-                    next_antinode.x -= distance.x;
-                    next_antinode.y -= distance.y;
-                    if is_in_bounds(&next_antinode, &extents) {
-                        extended_antinodes.insert(next_antinode.clone());
-                    } else {
-                        break;
-                    }
-                }
-            }
+    let rules = vec![
+        ("unique", AntinodeRule::Harmonics(vec![1])),
+        ("extended", AntinodeRule::AllHarmonics { gcd_step: false }),
+        ("lattice", AntinodeRule::AllHarmonics { gcd_step: true }),
+        ("ratio_2_1", AntinodeRule::Ratio(2, 1)),
+    ];
+
+    for &(_, ref rule) in &rules {
+        if let Err(error) = rule.validate() {
+            eprintln!("{}", error);
+            return;
         }
     }
 
-    println!(
-        "unique_len={}, extended_len={}",
-        unique_antinodes.len(),
-        extended_antinodes.len()
-    );
+    println!("[0, 0, {:?}]", extents);
+    let mut counts = Vec::new();
+    for &(name, ref rule) in &rules {
+        let mut all_antinodes: HashSet<Point> = HashSet::new();
+        let mut frequencies: Vec<&String> = input.keys().collect();
+        frequencies.sort();
+        for freq in frequencies {
+            let mut freq_antinodes: HashSet<Point> = HashSet::new();
+            for combination in input[freq].iter().combinations(2) {
+                freq_antinodes.extend(antinodes(rule, combination[0], combination[1], &extents));
+            }
+            if SHOW_FREQUENCIES {
+                println!("{} {}: {}", name, freq, freq_antinodes.len());
+            }
+            all_antinodes.extend(freq_antinodes);
+        }
+        if name == RENDER_RULE {
+            print!("{}", render(&input, &all_antinodes, &extents));
+        }
+        counts.push(format!("{}_len={}", name, all_antinodes.len()));
+    }
+    println!("{}", counts.join(", "));
 }
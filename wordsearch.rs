@@ -0,0 +1,483 @@
+// Word search engine shared by problem4.rs and problem4part2.rs, pulled in
+// with `mod wordsearch;`. Each word is expanded into templates, one per
+// distinct orientation of each shape, listing (dx, dy, letter) cells relative
+// to an anchor. Searching is then just checking every template at every cell.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Line directions with y growing downwards, as the grid is read.
+const LINE_DIRS: [(i32, i32, &str); 8] = [
+    (1, 0, "E"),
+    (1, 1, "SE"),
+    (0, 1, "S"),
+    (-1, 1, "SW"),
+    (-1, 0, "W"),
+    (-1, -1, "NW"),
+    (0, -1, "N"),
+    (1, -1, "NE"),
+];
+
+#[derive(Debug, Clone)]
+pub enum Shape {
+    // The word along a straight line in any of the 8 directions. Anchored on
+    // the first letter.
+    Line,
+    // Two copies of an odd length word crossing diagonally at the middle
+    // letter, each read in either direction. Anchored on the middle letter.
+    XCross,
+    // Like `XCross` but crossing horizontally and vertically.
+    Plus,
+    // Rows of a user supplied mask where the digit `i` is the word's i-th
+    // letter and any other character is ignored. Tried in all rotations and
+    // reflections and anchored on the top left of the mask.
+    Mask(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub word: String,
+    // Orientation of the placement, e.g. "SE" for a line or "SE/NE" for a
+    // cross read along both diagonals.
+    pub label: String,
+    pub cells: Vec<(i32, i32, char)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Match<'a> {
+    pub template: &'a Template,
+    pub x: usize,
+    pub y: usize,
+}
+
+pub struct Grid {
+    pub rows: Vec<Vec<char>>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Grid {
+    pub fn from_lines(lines: &[String]) -> Grid {
+        let rows: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let height = rows.len();
+        Grid {
+            rows: rows,
+            width: width,
+            height: height,
+        }
+    }
+
+    pub fn from_file(file_name: &str) -> Grid {
+        let file = File::open(file_name).expect("Unable to open file");
+        let reader = BufReader::new(file);
+        let lines: Vec<String> = reader
+            .lines()
+            .map(|l| l.expect("Unable to read line"))
+            .collect();
+        return Grid::from_lines(&lines);
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<char> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        return self
+            .rows
+            .get(y as usize)
+            .and_then(|row| row.get(x as usize))
+            .cloned();
+    }
+}
+
+// Cells reading `word` from its middle letter outwards along `dir`.
+fn centered_line(word: &[char], dir: (i32, i32)) -> Vec<(i32, i32, char)> {
+    let mid = (word.len() / 2) as i32;
+    return word
+        .iter()
+        .enumerate()
+        .map(|(i, c)| ((i as i32 - mid) * dir.0, (i as i32 - mid) * dir.1, *c))
+        .collect();
+}
+
+// Both readings of each axis, e.g. (SE or NW) crossed with (NE or SW).
+fn cross_templates(
+    word: &str,
+    axis1: [(i32, i32, &str); 2],
+    axis2: [(i32, i32, &str); 2],
+) -> Vec<Template> {
+    let chars: Vec<char> = word.chars().collect();
+    if chars.len() % 2 == 0 {
+        return Vec::new();
+    }
+
+    let mut templates = Vec::new();
+    for d1 in axis1.iter() {
+        for d2 in axis2.iter() {
+            let mut cells = centered_line(&chars, (d1.0, d1.1));
+            cells.extend(centered_line(&chars, (d2.0, d2.1)));
+            templates.push(Template {
+                word: word.to_string(),
+                label: format!("{}/{}", d1.2, d2.2),
+                cells: cells,
+            });
+        }
+    }
+    return templates;
+}
+
+fn mask_templates(word: &str, mask: &[String]) -> Vec<Template> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut cells = Vec::new();
+    for (y, row) in mask.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if let Some(d) = c.to_digit(10) {
+                if d as usize >= chars.len() {
+                    // Word is too short for this mask.
+                    return Vec::new();
+                }
+                cells.push((x as i32, y as i32, chars[d as usize]));
+            }
+        }
+    }
+
+    let mut templates = Vec::new();
+    for mirrored in [false, true].iter() {
+        for rotation in 0..4 {
+            let mut variant: Vec<(i32, i32, char)> = cells
+                .iter()
+                .map(|&(x, y, c)| {
+                    let (mut x, mut y) = (if *mirrored { -x } else { x }, y);
+                    for _ in 0..rotation {
+                        let (rx, ry) = (-y, x);
+                        x = rx;
+                        y = ry;
+                    }
+                    (x, y, c)
+                })
+                .collect();
+
+            // Re-anchor on the top left corner of the rotated mask.
+            let min_x = variant.iter().map(|c| c.0).min().unwrap_or(0);
+            let min_y = variant.iter().map(|c| c.1).min().unwrap_or(0);
+            for cell in variant.iter_mut() {
+                cell.0 -= min_x;
+                cell.1 -= min_y;
+            }
+
+            templates.push(Template {
+                word: word.to_string(),
+                label: format!("r{}{}", rotation * 90, if *mirrored { "m" } else { "" }),
+                cells: variant,
+            });
+        }
+    }
+    return templates;
+}
+
+fn shape_templates(word: &str, shape: &Shape) -> Vec<Template> {
+    match shape {
+        &Shape::Line => {
+            let chars: Vec<char> = word.chars().collect();
+            LINE_DIRS
+                .iter()
+                .map(|&(dx, dy, label)| Template {
+                    word: word.to_string(),
+                    label: label.to_string(),
+                    cells: chars
+                        .iter()
+                        .enumerate()
+                        .map(|(i, c)| (i as i32 * dx, i as i32 * dy, *c))
+                        .collect(),
+                })
+                .collect()
+        }
+        &Shape::XCross => cross_templates(
+            word,
+            [LINE_DIRS[1], LINE_DIRS[5]],
+            [LINE_DIRS[7], LINE_DIRS[3]],
+        ),
+        &Shape::Plus => cross_templates(
+            word,
+            [LINE_DIRS[0], LINE_DIRS[4]],
+            [LINE_DIRS[2], LINE_DIRS[6]],
+        ),
+        &Shape::Mask(ref mask) => mask_templates(word, mask),
+    }
+}
+
+pub struct WordSearch {
+    pub templates: Vec<Template>,
+}
+
+impl WordSearch {
+    // Expands every word in every shape. Templates covering the same letters
+    // at the same relative cells (e.g. palindromes, or "SAMX" next to "XMAS"
+    // read backwards) are only kept once so a placement isn't counted twice.
+    pub fn new(words: &[&str], shapes: &[Shape]) -> WordSearch {
+        let mut templates = Vec::new();
+        let mut seen = HashSet::new();
+        for word in words {
+            for shape in shapes {
+                for template in shape_templates(word, shape) {
+                    let min_x = template.cells.iter().map(|c| c.0).min().unwrap_or(0);
+                    let min_y = template.cells.iter().map(|c| c.1).min().unwrap_or(0);
+                    let mut key: Vec<(i32, i32, char)> = template
+                        .cells
+                        .iter()
+                        .map(|&(x, y, c)| (x - min_x, y - min_y, c))
+                        .collect();
+                    key.sort();
+                    key.dedup();
+                    if seen.insert(key) {
+                        templates.push(template);
+                    }
+                }
+            }
+        }
+        WordSearch {
+            templates: templates,
+        }
+    }
+
+    fn matches_at(&self, grid: &Grid, template: &Template, x: usize, y: usize) -> bool {
+        return template
+            .cells
+            .iter()
+            .all(|&(dx, dy, c)| grid.get(x as i32 + dx, y as i32 + dy) == Some(c));
+    }
+
+    // Every placement of every template, in row major order of the anchor.
+    pub fn find<'a>(&'a self, grid: &Grid) -> Vec<Match<'a>> {
+        let mut matches = Vec::new();
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                for template in &self.templates {
+                    if self.matches_at(grid, template, x, y) {
+                        matches.push(Match {
+                            template: template,
+                            x: x,
+                            y: y,
+                        });
+                    }
+                }
+            }
+        }
+        return matches;
+    }
+
+    pub fn count(&self, grid: &Grid) -> usize {
+        let mut count = 0;
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                for template in &self.templates {
+                    if self.matches_at(grid, template, x, y) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        return count;
+    }
+
+    // Letters that appear in any template, i.e. the only ones a `BitGrid`
+    // needs to track.
+    pub fn letters(&self) -> HashSet<char> {
+        let mut letters = HashSet::new();
+        for template in &self.templates {
+            for &(_, _, c) in &template.cells {
+                letters.insert(c);
+            }
+        }
+        return letters;
+    }
+
+    // Same result as `count()` but checks 64 anchors at a time. For each
+    // template and anchor row, the rows of every cell's letter mask are
+    // shifted so bit x lines up with anchor x, then ANDed together; the
+    // surviving bits are the matches.
+    pub fn count_bits(&self, bits: &BitGrid) -> usize {
+        let mut count = 0;
+        let mut acc = vec![0_u64; bits.words_per_row];
+        let mut shifted = vec![0_u64; bits.words_per_row];
+        for template in &self.templates {
+            for y in 0..bits.height {
+                for (w, a) in acc.iter_mut().enumerate() {
+                    *a = bits.valid_word(w);
+                }
+                for &(dx, dy, c) in &template.cells {
+                    let row_y = y as i32 + dy;
+                    if row_y < 0 || row_y as usize >= bits.height || !bits.letters.contains_key(&c)
+                    {
+                        for a in acc.iter_mut() {
+                            *a = 0;
+                        }
+                        break;
+                    }
+                    bits.shifted_row(c, row_y as usize, dx, &mut shifted);
+                    for (a, s) in acc.iter_mut().zip(shifted.iter()) {
+                        *a &= *s;
+                    }
+                }
+                count += acc.iter().map(|a| a.count_ones() as usize).sum::<usize>();
+            }
+        }
+        return count;
+    }
+}
+
+// One row major bitmask per letter, bit x of word x / 64 set if the grid has
+// that letter at x. Can be built straight from a file so huge grids never
+// need to be held as chars.
+pub struct BitGrid {
+    pub width: usize,
+    pub height: usize,
+    pub words_per_row: usize,
+    letters: HashMap<char, Vec<u64>>,
+}
+
+impl BitGrid {
+    pub fn from_lines<I: Iterator<Item = String>>(lines: I, letters: &HashSet<char>) -> BitGrid {
+        let mut rows: HashMap<char, Vec<Vec<u64>>> =
+            letters.iter().map(|c| (*c, Vec::new())).collect();
+        let mut width = 0;
+        let mut height = 0;
+        for line in lines {
+            let chars: Vec<char> = line.chars().collect();
+            width = std::cmp::max(width, chars.len());
+            for row in rows.values_mut() {
+                row.push(vec![0; (chars.len() + 63) / 64]);
+            }
+            for (x, c) in chars.iter().enumerate() {
+                if let Some(row) = rows.get_mut(c) {
+                    row[height][x / 64] |= 1 << (x % 64);
+                }
+            }
+            height += 1;
+        }
+
+        let words_per_row = (width + 63) / 64;
+        let letters = rows
+            .into_iter()
+            .map(|(c, letter_rows)| {
+                let mut flat = Vec::with_capacity(words_per_row * height);
+                for mut row in letter_rows {
+                    row.resize(words_per_row, 0);
+                    flat.extend(row);
+                }
+                (c, flat)
+            })
+            .collect();
+        BitGrid {
+            width: width,
+            height: height,
+            words_per_row: words_per_row,
+            letters: letters,
+        }
+    }
+
+    pub fn from_file(file_name: &str, letters: &HashSet<char>) -> BitGrid {
+        let file = File::open(file_name).expect("Unable to open file");
+        let reader = BufReader::new(file);
+        return BitGrid::from_lines(
+            reader.lines().map(|l| l.expect("Unable to read line")),
+            letters,
+        );
+    }
+
+    // Bits of word `w` that are inside the grid.
+    fn valid_word(&self, w: usize) -> u64 {
+        let end = std::cmp::min(self.width, (w + 1) * 64);
+        let bits = end - w * 64;
+        return if bits == 64 { !0 } else { (1 << bits) - 1 };
+    }
+
+    // Writes row `y` of letter `c` to `out` such that bit x of the output is
+    // bit x + dx of the row, reading zeroes past either edge.
+    fn shifted_row(&self, c: char, y: usize, dx: i32, out: &mut [u64]) {
+        let row = &self.letters[&c][y * self.words_per_row..(y + 1) * self.words_per_row];
+        let get = |w: i64| -> u64 {
+            if w < 0 || w >= row.len() as i64 {
+                0
+            } else {
+                row[w as usize]
+            }
+        };
+        let q = dx.abs() as i64 / 64;
+        let r = dx.abs() as u32 % 64;
+        for (w, o) in out.iter_mut().enumerate() {
+            let w = w as i64;
+            *o = if r == 0 {
+                get(w + q * dx.signum() as i64)
+            } else if dx > 0 {
+                (get(w + q) >> r) | (get(w + q + 1) << (64 - r))
+            } else {
+                (get(w - q) << r) | (get(w - q - 1) >> (64 - r))
+            };
+        }
+    }
+}
+
+// Grid with every letter that isn't part of some match replaced by '.'.
+pub fn render(grid: &Grid, matches: &[Match]) -> String {
+    let mut keep = vec![vec![false; grid.width]; grid.height];
+    for m in matches {
+        for &(dx, dy, _) in &m.template.cells {
+            keep[(m.y as i32 + dy) as usize][(m.x as i32 + dx) as usize] = true;
+        }
+    }
+
+    let mut output = String::new();
+    for (y, row) in grid.rows.iter().enumerate() {
+        for (x, c) in row.iter().enumerate() {
+            output.push(if keep[y][x] { *c } else { '.' });
+        }
+        output.push('\n');
+    }
+    return output;
+}
+
+pub fn to_csv(matches: &[Match]) -> String {
+    let mut output = String::from("word,x,y,orientation\n");
+    for m in matches {
+        output += &format!("{},{},{},{}\n", m.template.word, m.x, m.y, m.template.label);
+    }
+    return output;
+}
+
+fn json_string(s: &str) -> String {
+    return format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+}
+
+pub fn to_json(matches: &[Match]) -> String {
+    let entries: Vec<String> = matches
+        .iter()
+        .map(|m| {
+            format!(
+                "  {{\"word\": {}, \"x\": {}, \"y\": {}, \"orientation\": {}}}",
+                json_string(&m.template.word),
+                m.x,
+                m.y,
+                json_string(&m.template.label)
+            )
+        })
+        .collect();
+    return format!("[\n{}\n]\n", entries.join(",\n"));
+}
+
+// Prints the matches in `format` ("csv", "json" or "" for none), followed by
+// the highlighted grid if `show_grid` is set.
+pub fn print_matches(grid: &Grid, matches: &[Match], format: &str, show_grid: bool) {
+    match format {
+        "csv" => print!("{}", to_csv(matches)),
+        "json" => print!("{}", to_json(matches)),
+        "" => {}
+        _ => panic!("Unknown export format: {}", format),
+    }
+    if show_grid {
+        print!("{}", render(grid, matches));
+    }
+}